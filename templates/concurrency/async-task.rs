@@ -30,6 +30,9 @@ async fn basic_spawn() {
 // Concurrent Tasks with JoinSet
 // =====================================================
 
+// A URL listed twice is fetched twice here; see single-flight.rs to
+// coalesce duplicate requests.
+
 async fn parallel_fetch(urls: Vec<String>) -> Vec<String> {
     let mut set = JoinSet::new();
 
@@ -57,6 +60,7 @@ async fn parallel_fetch(urls: Vec<String>) -> Vec<String> {
 async fn cancellable_task(cancel: oneshot::Receiver<()>) {
     tokio::select! {
         _ = async {
+            // For real periodic work, see scheduler.rs
             loop {
                 println!("Working...");
                 tokio::time::sleep(Duration::from_millis(500)).await;
@@ -96,7 +100,7 @@ async fn producer_consumer() {
         }
     });
 
-    // Consumer (slower)
+    // Consumer (slower); see channel-adapters.rs to consume in batches
     let consumer = tokio::spawn(async move {
         while let Some(item) = rx.recv().await {
             tokio::time::sleep(Duration::from_millis(50)).await;
@@ -111,6 +115,9 @@ async fn producer_consumer() {
 // Task with Timeout
 // =====================================================
 
+// A single local timeout. For a budget shared by nested calls and
+// spawned tasks, see deadline.rs.
+
 async fn with_timeout<F, T>(future: F, timeout: Duration) -> Result<T, &'static str>
 where
     F: std::future::Future<Output = T>,
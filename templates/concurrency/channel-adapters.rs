@@ -0,0 +1,328 @@
+//! Channel adapters: batching, debounce, throttle and coalescing
+//!
+//! Wrap an `mpsc::Receiver` so the consumer pulls batches or rate-limited
+//! items instead of reading one message at a time.
+//!
+//! Add to Cargo.toml:
+//! ```toml
+//! [dependencies]
+//! tokio = { version = "1", features = ["full"] }
+//!
+//! [dev-dependencies]
+//! tokio = { version = "1", features = ["full", "test-util"] }
+//! ```
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Instant};
+
+// =====================================================
+// Extension Trait
+// =====================================================
+
+pub trait ReceiverExt<T>: Sized {
+    /// Yield batches of up to `max_items`, or whatever arrived within
+    /// `period` of the first item in the batch.
+    fn chunks_timeout(self, max_items: usize, period: Duration) -> ChunksTimeout<T>;
+
+    /// Yield the latest item once no new item arrived for `quiet`.
+    fn debounce(self, quiet: Duration) -> Debounce<T>;
+
+    /// Yield at most one item per `interval`; nothing is dropped.
+    fn throttle(self, interval: Duration) -> Throttle<T>;
+
+    /// Like `chunks_timeout`, but keep only the latest item per key.
+    fn coalesce_by_key<K, F>(self, window: Duration, key: F) -> Coalesce<T, K, F>
+    where
+        K: Eq + Hash,
+        F: FnMut(&T) -> K;
+}
+
+impl<T> ReceiverExt<T> for mpsc::Receiver<T> {
+    fn chunks_timeout(self, max_items: usize, period: Duration) -> ChunksTimeout<T> {
+        assert!(max_items > 0, "max_items must be positive");
+        ChunksTimeout { rx: self, max_items, period }
+    }
+
+    fn debounce(self, quiet: Duration) -> Debounce<T> {
+        Debounce { rx: self, quiet }
+    }
+
+    fn throttle(self, interval: Duration) -> Throttle<T> {
+        Throttle { rx: self, interval, next_allowed: None }
+    }
+
+    fn coalesce_by_key<K, F>(self, window: Duration, key: F) -> Coalesce<T, K, F>
+    where
+        K: Eq + Hash,
+        F: FnMut(&T) -> K,
+    {
+        Coalesce { rx: self, window, key, _key: std::marker::PhantomData }
+    }
+}
+
+// =====================================================
+// Time-or-Size Batching
+// =====================================================
+
+pub struct ChunksTimeout<T> {
+    rx: mpsc::Receiver<T>,
+    max_items: usize,
+    period: Duration,
+}
+
+impl<T> ChunksTimeout<T> {
+    /// Next non-empty batch, `None` once the channel is closed and drained.
+    pub async fn next(&mut self) -> Option<Vec<T>> {
+        // Block until there is something to batch; no empty batches
+        let first = self.rx.recv().await?;
+        let deadline = Instant::now() + self.period;
+
+        let mut batch = Vec::with_capacity(self.max_items);
+        batch.push(first);
+
+        while batch.len() < self.max_items {
+            tokio::select! {
+                item = self.rx.recv() => match item {
+                    Some(item) => batch.push(item),
+                    None => break,
+                },
+                _ = sleep_until(deadline) => break,
+            }
+        }
+
+        Some(batch)
+    }
+}
+
+// =====================================================
+// Debounce
+// =====================================================
+
+pub struct Debounce<T> {
+    rx: mpsc::Receiver<T>,
+    quiet: Duration,
+}
+
+impl<T> Debounce<T> {
+    /// Latest item of the next burst, `None` once the channel is closed.
+    pub async fn next(&mut self) -> Option<T> {
+        let mut latest = self.rx.recv().await?;
+
+        loop {
+            tokio::select! {
+                item = self.rx.recv() => match item {
+                    // New item restarts the quiet period
+                    Some(item) => latest = item,
+                    // Flush the pending item on close
+                    None => return Some(latest),
+                },
+                _ = tokio::time::sleep(self.quiet) => return Some(latest),
+            }
+        }
+    }
+}
+
+// =====================================================
+// Throttle
+// =====================================================
+
+pub struct Throttle<T> {
+    rx: mpsc::Receiver<T>,
+    interval: Duration,
+    next_allowed: Option<Instant>,
+}
+
+impl<T> Throttle<T> {
+    /// Next item, delayed so items are at least `interval` apart.
+    pub async fn next(&mut self) -> Option<T> {
+        if let Some(at) = self.next_allowed {
+            sleep_until(at).await;
+        }
+
+        let item = self.rx.recv().await?;
+        self.next_allowed = Some(Instant::now() + self.interval);
+        Some(item)
+    }
+}
+
+// =====================================================
+// Coalesce by Key
+// =====================================================
+
+pub struct Coalesce<T, K, F> {
+    rx: mpsc::Receiver<T>,
+    window: Duration,
+    key: F,
+    _key: std::marker::PhantomData<fn() -> K>,
+}
+
+impl<T, K, F> Coalesce<T, K, F>
+where
+    K: Eq + Hash,
+    F: FnMut(&T) -> K,
+{
+    /// Latest item per key seen within one window, in first-seen key order.
+    pub async fn next(&mut self) -> Option<Vec<T>> {
+        let first = self.rx.recv().await?;
+        let deadline = Instant::now() + self.window;
+
+        let mut slots: HashMap<K, usize> = HashMap::new();
+        let mut batch = Vec::new();
+        self.insert(&mut slots, &mut batch, first);
+
+        loop {
+            tokio::select! {
+                item = self.rx.recv() => match item {
+                    Some(item) => self.insert(&mut slots, &mut batch, item),
+                    None => break,
+                },
+                _ = sleep_until(deadline) => break,
+            }
+        }
+
+        Some(batch)
+    }
+
+    fn insert(&mut self, slots: &mut HashMap<K, usize>, batch: &mut Vec<T>, item: T) {
+        let key = (self.key)(&item);
+        match slots.get(&key) {
+            Some(&index) => batch[index] = item,
+            None => {
+                slots.insert(key, batch.len());
+                batch.push(item);
+            }
+        }
+    }
+}
+
+// =====================================================
+// Example: Batched Database Writer
+// =====================================================
+
+async fn write_batch(rows: &[i32]) {
+    // Simulate one round trip for the whole batch
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    println!("Wrote {} rows: {:?}", rows.len(), rows);
+}
+
+async fn batched_consumer() {
+    let (tx, rx) = mpsc::channel::<i32>(100);
+
+    let producer = tokio::spawn(async move {
+        for i in 0..25 {
+            tx.send(i).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    });
+
+    // Up to 10 rows per write, or every 50ms
+    let mut batches = rx.chunks_timeout(10, Duration::from_millis(50));
+    while let Some(rows) = batches.next().await {
+        write_batch(&rows).await;
+    }
+
+    let _ = producer.await;
+}
+
+// =====================================================
+// Main
+// =====================================================
+
+#[tokio::main]
+async fn main() {
+    batched_consumer().await;
+}
+
+// =====================================================
+// Tests
+// =====================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_chunks_by_size_then_time() {
+        let (tx, rx) = mpsc::channel(16);
+        let mut chunks = rx.chunks_timeout(3, Duration::from_millis(100));
+
+        for i in 0..4 {
+            tx.send(i).await.unwrap();
+        }
+
+        assert_eq!(chunks.next().await, Some(vec![0, 1, 2]));
+
+        // Only one item left: flushed when the period elapses
+        let start = Instant::now();
+        assert_eq!(chunks.next().await, Some(vec![3]));
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+
+        drop(tx);
+        assert_eq!(chunks.next().await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_debounce_keeps_latest_of_burst() {
+        let (tx, rx) = mpsc::channel(16);
+        let mut debounced = rx.debounce(Duration::from_millis(50));
+
+        tokio::spawn(async move {
+            for i in 0..3 {
+                tx.send(i).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            tx.send(10).await.unwrap();
+        });
+
+        assert_eq!(debounced.next().await, Some(2));
+        assert_eq!(debounced.next().await, Some(10));
+        assert_eq!(debounced.next().await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_spaces_items() {
+        let (tx, rx) = mpsc::channel(16);
+        let mut throttled = rx.throttle(Duration::from_millis(100));
+
+        for i in 0..3 {
+            tx.send(i).await.unwrap();
+        }
+        drop(tx);
+
+        let start = Instant::now();
+        let mut seen = Vec::new();
+        while let Some(item) = throttled.next().await {
+            seen.push((item, start.elapsed()));
+        }
+
+        assert_eq!(
+            seen,
+            vec![
+                (0, Duration::ZERO),
+                (1, Duration::from_millis(100)),
+                (2, Duration::from_millis(200)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_keeps_latest_per_key() {
+        let (tx, rx) = mpsc::channel(16);
+        let mut coalesced = rx.coalesce_by_key(Duration::from_millis(50), |(k, _)| *k);
+
+        for update in [("a", 1), ("b", 1), ("a", 2), ("c", 1), ("b", 2)] {
+            tx.send(update).await.unwrap();
+        }
+        drop(tx);
+
+        assert_eq!(
+            coalesced.next().await,
+            Some(vec![("a", 2), ("b", 2), ("c", 1)])
+        );
+        assert_eq!(coalesced.next().await, None);
+    }
+}
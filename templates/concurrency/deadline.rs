@@ -0,0 +1,287 @@
+//! Deadline propagation across nested async calls
+//!
+//! `tokio::time::timeout` bounds a single future. A deadline is a budget for a
+//! whole request: it flows through nested calls and spawned tasks, inner calls
+//! can only tighten it, and whichever stage runs out of time reports itself.
+//!
+//! Add to Cargo.toml:
+//! ```toml
+//! [dependencies]
+//! tokio = { version = "1", features = ["full"] }
+//! thiserror = "1"
+//!
+//! [dev-dependencies]
+//! tokio = { version = "1", features = ["full", "test-util"] }
+//! ```
+
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+// =====================================================
+// Deadline Context
+// =====================================================
+
+tokio::task_local! {
+    static DEADLINE: Deadline;
+}
+
+/// The budget in effect for the current task.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    expires_at: Instant,
+    budget: Duration,
+}
+
+impl Deadline {
+    /// Point in time at which the budget is exhausted
+    pub fn expires_at(&self) -> Instant {
+        self.expires_at
+    }
+
+    /// Total budget of the scope that set this deadline
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// Time left before the deadline (zero once expired)
+    pub fn remaining(&self) -> Duration {
+        self.expires_at.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires_at
+    }
+}
+
+/// Deadline of the current task, if any scope set one.
+pub fn current() -> Option<Deadline> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Remaining budget of the current task, `None` if unbounded.
+pub fn remaining() -> Option<Duration> {
+    current().map(|deadline| deadline.remaining())
+}
+
+// =====================================================
+// Error Type
+// =====================================================
+
+/// The budget ran out while `stage` was running.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("deadline of {budget:?} exceeded in stage `{stage}`")]
+pub struct DeadlineExceeded {
+    pub stage: &'static str,
+    pub budget: Duration,
+}
+
+// =====================================================
+// Scoping and Stages
+// =====================================================
+
+/// Run `future` with at most `budget` left.
+///
+/// A nested scope can only shorten the deadline of its caller, never extend it.
+pub async fn with_deadline<F>(budget: Duration, future: F) -> F::Output
+where
+    F: Future,
+{
+    let requested = Deadline {
+        expires_at: Instant::now() + budget,
+        budget,
+    };
+
+    let deadline = match current() {
+        Some(parent) if parent.expires_at <= requested.expires_at => parent,
+        _ => requested,
+    };
+
+    DEADLINE.scope(deadline, future).await
+}
+
+/// Run one named step of a request against the current deadline.
+///
+/// The innermost stage that is running when the budget runs out is the one
+/// reported, so nesting with `?` keeps the most specific stage name.
+pub async fn stage<F, T, E>(name: &'static str, future: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: From<DeadlineExceeded>,
+{
+    let Some(deadline) = current() else {
+        return future.await;
+    };
+
+    let exceeded = DeadlineExceeded {
+        stage: name,
+        budget: deadline.budget,
+    };
+
+    // Don't start work that cannot finish in time
+    if deadline.is_expired() {
+        return Err(exceeded.into());
+    }
+
+    match tokio::time::timeout_at(deadline.expires_at, future).await {
+        Ok(result) => result,
+        Err(_) => Err(exceeded.into()),
+    }
+}
+
+/// `tokio::spawn` that carries the current deadline into the new task.
+///
+/// Task-locals are not inherited by spawned tasks, so plain `tokio::spawn`
+/// would silently drop the budget.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match current() {
+        Some(deadline) => tokio::spawn(DEADLINE.scope(deadline, future)),
+        None => tokio::spawn(future),
+    }
+}
+
+// =====================================================
+// Example: Request Handler with Nested Stages
+// =====================================================
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error(transparent)]
+    Deadline(#[from] DeadlineExceeded),
+
+    #[error("backend error: {0}")]
+    Backend(String),
+}
+
+async fn fetch_user(id: u64) -> Result<String, RequestError> {
+    stage("fetch_user", async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        Ok(format!("user-{}", id))
+    })
+    .await
+}
+
+async fn fetch_orders(user: &str) -> Result<Vec<String>, RequestError> {
+    // Cap this call even if the caller has more budget left
+    with_deadline(Duration::from_millis(200), async {
+        stage("fetch_orders", async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(vec![format!("{}-order-1", user)])
+        })
+        .await
+    })
+    .await
+}
+
+async fn handle_request(id: u64) -> Result<Vec<String>, RequestError> {
+    stage("handle_request", async {
+        let user = fetch_user(id).await?;
+
+        // Background audit write shares the request deadline
+        let audit = spawn(stage("audit", async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok::<_, RequestError>(())
+        }));
+
+        let orders = fetch_orders(&user).await?;
+        audit
+            .await
+            .map_err(|e| RequestError::Backend(e.to_string()))??;
+
+        Ok(orders)
+    })
+    .await
+}
+
+// =====================================================
+// Main
+// =====================================================
+
+#[tokio::main]
+async fn main() {
+    let result = with_deadline(Duration::from_secs(1), handle_request(42)).await;
+    println!("Generous budget: {:?}", result);
+
+    let result = with_deadline(Duration::from_millis(100), handle_request(42)).await;
+    println!("Tight budget: {:?}", result);
+}
+
+// =====================================================
+// Tests
+// =====================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_remaining_shrinks() {
+        assert_eq!(remaining(), None);
+
+        with_deadline(Duration::from_secs(1), async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            assert_eq!(remaining(), Some(Duration::from_millis(700)));
+        })
+        .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_nested_scope_cannot_extend() {
+        with_deadline(Duration::from_millis(100), async {
+            with_deadline(Duration::from_secs(10), async {
+                assert_eq!(remaining(), Some(Duration::from_millis(100)));
+            })
+            .await;
+
+            with_deadline(Duration::from_millis(20), async {
+                assert_eq!(remaining(), Some(Duration::from_millis(20)));
+            })
+            .await;
+        })
+        .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_innermost_stage_reported() {
+        let err = with_deadline(Duration::from_millis(120), handle_request(1))
+            .await
+            .unwrap_err();
+
+        match err {
+            RequestError::Deadline(e) => {
+                assert_eq!(e.stage, "fetch_orders");
+                assert_eq!(e.budget, Duration::from_millis(120));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_spawned_task_inherits_deadline() {
+        with_deadline(Duration::from_millis(50), async {
+            let handle = spawn(async { remaining() });
+            assert_eq!(handle.await.unwrap(), Some(Duration::from_millis(50)));
+
+            let plain = tokio::spawn(async { remaining() });
+            assert_eq!(plain.await.unwrap(), None);
+        })
+        .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_expired_stage_fails_fast() {
+        let result: Result<(), DeadlineExceeded> =
+            with_deadline(Duration::from_millis(10), async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                stage("late", async { panic!("should not run") }).await
+            })
+            .await;
+
+        assert_eq!(result.unwrap_err().stage, "late");
+    }
+}
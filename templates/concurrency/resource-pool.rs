@@ -0,0 +1,501 @@
+//! Generic async resource pool (connections, clients, sessions)
+//!
+//! A `Manager` knows how to create, recycle and health-check one kind of
+//! resource; the `Pool` bounds how many exist, hands them out behind RAII
+//! guards and evicts the ones that sat idle too long.
+//!
+//! Add to Cargo.toml:
+//! ```toml
+//! [dependencies]
+//! tokio = { version = "1", features = ["full"] }
+//! thiserror = "1"
+//!
+//! [dev-dependencies]
+//! tokio = { version = "1", features = ["full", "test-util"] }
+//! ```
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+// =====================================================
+// Manager Trait
+// =====================================================
+
+/// Lifecycle hooks for one kind of pooled resource.
+pub trait Manager: Send + Sync + 'static {
+    type Resource: Send + 'static;
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Open a brand new resource.
+    fn create(&self) -> impl Future<Output = Result<Self::Resource, Self::Error>> + Send;
+
+    /// Reset an idle resource before handing it out again.
+    /// An error discards the resource and the pool tries the next one.
+    fn recycle(
+        &self,
+        resource: &mut Self::Resource,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Health check run on idle resources by maintenance.
+    fn validate(&self, resource: &Self::Resource) -> impl Future<Output = bool> + Send;
+}
+
+// =====================================================
+// Error Type
+// =====================================================
+
+#[derive(Debug, thiserror::Error)]
+pub enum PoolError<E> {
+    #[error("timed out waiting for a pooled resource")]
+    Timeout,
+
+    #[error("pool is closed")]
+    Closed,
+
+    #[error("failed to create resource")]
+    Backend(#[source] E),
+}
+
+// =====================================================
+// Builder
+// =====================================================
+
+pub struct PoolBuilder<M> {
+    manager: M,
+    max_size: usize,
+    acquire_timeout: Duration,
+    idle_timeout: Option<Duration>,
+}
+
+impl<M: Manager> PoolBuilder<M> {
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn acquire_timeout(mut self, timeout: Duration) -> Self {
+        self.acquire_timeout = timeout;
+        self
+    }
+
+    /// Discard resources that were idle for longer than `timeout`.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    pub fn build(self) -> Pool<M> {
+        assert!(self.max_size > 0, "max_size must be positive");
+
+        Pool {
+            inner: Arc::new(Inner {
+                manager: self.manager,
+                max_size: self.max_size,
+                acquire_timeout: self.acquire_timeout,
+                idle_timeout: self.idle_timeout,
+                idle: Mutex::new(VecDeque::new()),
+                permits: Arc::new(Semaphore::new(self.max_size)),
+            }),
+        }
+    }
+}
+
+// =====================================================
+// Pool
+// =====================================================
+
+struct IdleEntry<R> {
+    resource: R,
+    returned_at: Instant,
+}
+
+struct Inner<M: Manager> {
+    manager: M,
+    max_size: usize,
+    acquire_timeout: Duration,
+    idle_timeout: Option<Duration>,
+    idle: Mutex<VecDeque<IdleEntry<M::Resource>>>,
+    // One permit per checked-out resource; idle ones hold none. Resources
+    // are only created when nothing is idle, which is what keeps the total
+    // at max_size
+    permits: Arc<Semaphore>,
+}
+
+impl<M: Manager> Inner<M> {
+    fn is_stale(&self, entry: &IdleEntry<M::Resource>) -> bool {
+        self.idle_timeout
+            .is_some_and(|timeout| entry.returned_at.elapsed() >= timeout)
+    }
+
+    fn pop_idle(&self) -> Option<IdleEntry<M::Resource>> {
+        // Most recently returned first: keeps the working set small so
+        // the rest can age out
+        self.idle.lock().unwrap().pop_back()
+    }
+}
+
+/// Snapshot of pool occupancy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    pub max_size: usize,
+    pub in_use: usize,
+    pub idle: usize,
+}
+
+pub struct Pool<M: Manager> {
+    inner: Arc<Inner<M>>,
+}
+
+impl<M: Manager> Clone for Pool<M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<M: Manager> Pool<M> {
+    pub fn builder(manager: M) -> PoolBuilder<M> {
+        PoolBuilder {
+            manager,
+            max_size: 10,
+            acquire_timeout: Duration::from_secs(30),
+            idle_timeout: None,
+        }
+    }
+
+    /// Check out a resource, waiting at most `acquire_timeout`.
+    pub async fn get(&self) -> Result<Pooled<M>, PoolError<M::Error>> {
+        match tokio::time::timeout(self.inner.acquire_timeout, self.acquire()).await {
+            Ok(result) => result,
+            Err(_) => Err(PoolError::Timeout),
+        }
+    }
+
+    async fn acquire(&self) -> Result<Pooled<M>, PoolError<M::Error>> {
+        let permit = Arc::clone(&self.inner.permits)
+            .acquire_owned()
+            .await
+            .map_err(|_| PoolError::Closed)?;
+
+        while let Some(mut entry) = self.inner.pop_idle() {
+            if self.inner.is_stale(&entry) {
+                continue;
+            }
+            if self.inner.manager.recycle(&mut entry.resource).await.is_ok() {
+                return Ok(self.wrap(entry.resource, permit));
+            }
+        }
+
+        let resource = self
+            .inner
+            .manager
+            .create()
+            .await
+            .map_err(PoolError::Backend)?;
+
+        Ok(self.wrap(resource, permit))
+    }
+
+    fn wrap(&self, resource: M::Resource, permit: OwnedSemaphorePermit) -> Pooled<M> {
+        Pooled {
+            resource: Some(resource),
+            pool: Arc::downgrade(&self.inner),
+            _permit: permit,
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        let idle = self.inner.idle.lock().unwrap().len();
+        let in_use = self.inner.max_size - self.inner.permits.available_permits();
+        Status {
+            max_size: self.inner.max_size,
+            in_use,
+            idle,
+        }
+    }
+
+    /// Drop idle resources past `idle_timeout`, then health-check the rest.
+    pub async fn run_maintenance(&self) {
+        // Take the idle set out so the lock is not held across `.await`
+        let entries: Vec<_> = self.inner.idle.lock().unwrap().drain(..).collect();
+
+        let mut healthy = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if self.inner.is_stale(&entry) {
+                continue;
+            }
+            if self.inner.manager.validate(&entry.resource).await {
+                healthy.push(entry);
+            }
+        }
+
+        self.inner.idle.lock().unwrap().extend(healthy);
+    }
+
+    /// Run maintenance every `interval` until the pool is dropped.
+    pub fn spawn_maintenance(&self, interval: Duration) -> JoinHandle<()> {
+        let weak = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some(inner) = weak.upgrade() else { break };
+                Pool { inner }.run_maintenance().await;
+            }
+        })
+    }
+
+    /// Stop handing out resources; waiters get `PoolError::Closed`.
+    pub fn close(&self) {
+        self.inner.permits.close();
+        self.inner.idle.lock().unwrap().clear();
+    }
+}
+
+// =====================================================
+// RAII Guard
+// =====================================================
+
+/// A checked-out resource, returned to the pool on drop.
+pub struct Pooled<M: Manager> {
+    resource: Option<M::Resource>,
+    pool: Weak<Inner<M>>,
+    // Released after `drop` has put the resource back
+    _permit: OwnedSemaphorePermit,
+}
+
+impl<M: Manager> Pooled<M> {
+    /// Take the resource out of the pool for good (e.g. after a protocol error).
+    pub fn detach(mut self) -> M::Resource {
+        self.resource.take().expect("resource present until drop")
+    }
+}
+
+impl<M: Manager> Deref for Pooled<M> {
+    type Target = M::Resource;
+
+    fn deref(&self) -> &Self::Target {
+        self.resource.as_ref().expect("resource present until drop")
+    }
+}
+
+impl<M: Manager> DerefMut for Pooled<M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.resource.as_mut().expect("resource present until drop")
+    }
+}
+
+impl<M: Manager> Drop for Pooled<M> {
+    fn drop(&mut self) {
+        let (Some(resource), Some(pool)) = (self.resource.take(), self.pool.upgrade()) else {
+            return;
+        };
+        if pool.permits.is_closed() {
+            return;
+        }
+        pool.idle.lock().unwrap().push_back(IdleEntry {
+            resource,
+            returned_at: Instant::now(),
+        });
+    }
+}
+
+// =====================================================
+// Example: In-Process Fake Connection
+// =====================================================
+
+#[derive(Debug, thiserror::Error)]
+#[error("fake backend unavailable")]
+pub struct FakeError;
+
+pub struct FakeConn {
+    id: usize,
+    healthy: Arc<AtomicBool>,
+    in_transaction: bool,
+}
+
+impl FakeConn {
+    pub async fn query(&mut self, sql: &str) -> String {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        format!("conn {} ran {}", self.id, sql)
+    }
+}
+
+#[derive(Default)]
+pub struct FakeManager {
+    created: AtomicUsize,
+    fail_create: AtomicBool,
+    // Last connection's health flag, so tests can break it
+    last_health: Mutex<Option<Arc<AtomicBool>>>,
+}
+
+impl Manager for FakeManager {
+    type Resource = FakeConn;
+    type Error = FakeError;
+
+    async fn create(&self) -> Result<FakeConn, FakeError> {
+        if self.fail_create.load(Ordering::SeqCst) {
+            return Err(FakeError);
+        }
+        let healthy = Arc::new(AtomicBool::new(true));
+        *self.last_health.lock().unwrap() = Some(Arc::clone(&healthy));
+        Ok(FakeConn {
+            id: self.created.fetch_add(1, Ordering::SeqCst),
+            healthy,
+            in_transaction: false,
+        })
+    }
+
+    async fn recycle(&self, conn: &mut FakeConn) -> Result<(), FakeError> {
+        if !conn.healthy.load(Ordering::SeqCst) {
+            return Err(FakeError);
+        }
+        // Roll back whatever the previous user left open
+        conn.in_transaction = false;
+        Ok(())
+    }
+
+    async fn validate(&self, conn: &FakeConn) -> bool {
+        conn.healthy.load(Ordering::SeqCst)
+    }
+}
+
+// =====================================================
+// Main
+// =====================================================
+
+#[tokio::main]
+async fn main() {
+    let pool = Pool::builder(FakeManager::default())
+        .max_size(4)
+        .acquire_timeout(Duration::from_secs(1))
+        .idle_timeout(Duration::from_secs(60))
+        .build();
+    let _maintenance = pool.spawn_maintenance(Duration::from_secs(30));
+
+    let mut handles = Vec::new();
+    for i in 0..8 {
+        let pool = pool.clone();
+        handles.push(tokio::spawn(async move {
+            let mut conn = pool.get().await.expect("pool exhausted");
+            conn.query(&format!("SELECT {}", i)).await
+        }));
+    }
+
+    for handle in handles {
+        println!("{}", handle.await.unwrap());
+    }
+    println!("Status: {:?}", pool.status());
+}
+
+// =====================================================
+// Tests
+// =====================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(max_size: usize) -> Pool<FakeManager> {
+        Pool::builder(FakeManager::default())
+            .max_size(max_size)
+            .acquire_timeout(Duration::from_millis(100))
+            .idle_timeout(Duration::from_secs(10))
+            .build()
+    }
+
+    fn break_last_conn(pool: &Pool<FakeManager>) {
+        let last = pool.inner.manager.last_health.lock().unwrap();
+        last.as_ref().unwrap().store(false, Ordering::SeqCst);
+    }
+
+    #[tokio::test]
+    async fn test_resource_is_reused() {
+        let pool = pool(2);
+
+        let mut conn = pool.get().await.unwrap();
+        conn.in_transaction = true;
+        let id = conn.id;
+        drop(conn);
+
+        let conn = pool.get().await.unwrap();
+        assert_eq!(conn.id, id);
+        assert!(!conn.in_transaction, "recycle resets state");
+        assert_eq!(pool.inner.manager.created.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_acquire_times_out_at_max_size() {
+        let pool = pool(1);
+
+        let held = pool.get().await.unwrap();
+        assert_eq!(pool.status().in_use, 1);
+        assert!(matches!(pool.get().await, Err(PoolError::Timeout)));
+
+        drop(held);
+        assert!(pool.get().await.is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_resources_are_evicted() {
+        let pool = pool(2);
+        drop(pool.get().await.unwrap());
+        assert_eq!(pool.status().idle, 1);
+
+        tokio::time::advance(Duration::from_secs(11)).await;
+        pool.run_maintenance().await;
+        assert_eq!(pool.status().idle, 0);
+
+        let conn = pool.get().await.unwrap();
+        assert_eq!(conn.id, 1, "stale connection replaced");
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_resources_are_dropped() {
+        let pool = pool(2);
+        drop(pool.get().await.unwrap());
+        break_last_conn(&pool);
+
+        // Checkout skips the broken connection via recycle()
+        let conn = pool.get().await.unwrap();
+        assert_eq!(conn.id, 1);
+        drop(conn);
+
+        // Maintenance drops it via validate()
+        break_last_conn(&pool);
+        pool.run_maintenance().await;
+        assert_eq!(pool.status().idle, 0);
+    }
+
+    #[tokio::test]
+    async fn test_create_error_and_detach() {
+        let pool = pool(1);
+        pool.inner.manager.fail_create.store(true, Ordering::SeqCst);
+        assert!(matches!(pool.get().await, Err(PoolError::Backend(FakeError))));
+
+        // A failed create must not leak the permit
+        pool.inner.manager.fail_create.store(false, Ordering::SeqCst);
+        let conn = pool.get().await.unwrap().detach();
+        assert_eq!(conn.id, 0);
+        assert_eq!(pool.status(), Status { max_size: 1, in_use: 0, idle: 0 });
+    }
+
+    #[tokio::test]
+    async fn test_close_rejects_waiters() {
+        let pool = pool(1);
+        let held = pool.get().await.unwrap();
+
+        pool.close();
+        assert!(matches!(pool.get().await, Err(PoolError::Closed)));
+        drop(held);
+        assert_eq!(pool.status().idle, 0);
+    }
+}
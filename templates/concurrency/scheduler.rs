@@ -0,0 +1,566 @@
+//! Periodic job scheduler on tokio
+//!
+//! Fixed-rate, fixed-delay and cron schedules with a policy for missed
+//! ticks, overlap prevention, jitter and cancellation.
+//!
+//! Add to Cargo.toml:
+//! ```toml
+//! [dependencies]
+//! tokio = { version = "1", features = ["full"] }
+//! thiserror = "1"
+//!
+//! [dev-dependencies]
+//! tokio = { version = "1", features = ["full", "test-util"] }
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{sleep_until, Instant};
+
+// =====================================================
+// Schedule Definition
+// =====================================================
+
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Start a run every `period`, measured from the previous scheduled start
+    FixedRate(Duration),
+    /// Wait `delay` after a run finishes before starting the next one
+    FixedDelay(Duration),
+    /// Wall-clock schedule (UTC)
+    Cron(CronExpr),
+}
+
+/// What to do when a run took longer than the time to the next tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissedTick {
+    /// Drop the missed ticks and wait for the next one on the original grid
+    Skip,
+    /// Run once per missed tick, back to back, until caught up
+    Burst,
+    /// Run once now and restart the schedule from here
+    Delay,
+}
+
+pub struct Job {
+    schedule: Schedule,
+    missed_tick: MissedTick,
+    jitter: Duration,
+    allow_overlap: bool,
+}
+
+impl Job {
+    /// Fires immediately, then every `period`.
+    pub fn fixed_rate(period: Duration) -> Self {
+        assert!(!period.is_zero(), "period must be positive");
+        Self::new(Schedule::FixedRate(period))
+    }
+
+    /// Fires immediately, then `delay` after each run completes.
+    pub fn fixed_delay(delay: Duration) -> Self {
+        Self::new(Schedule::FixedDelay(delay))
+    }
+
+    /// Five-field cron expression: `minute hour day-of-month month day-of-week`.
+    pub fn cron(expr: &str) -> Result<Self, CronError> {
+        Ok(Self::new(Schedule::Cron(expr.parse()?)))
+    }
+
+    fn new(schedule: Schedule) -> Self {
+        Self {
+            schedule,
+            missed_tick: MissedTick::Skip,
+            jitter: Duration::ZERO,
+            allow_overlap: false,
+        }
+    }
+
+    pub fn missed_tick(mut self, policy: MissedTick) -> Self {
+        self.missed_tick = policy;
+        self
+    }
+
+    /// Delay each run by a random amount in `[0, max)` to spread load
+    /// across instances. The schedule grid itself does not drift.
+    pub fn jitter(mut self, max: Duration) -> Self {
+        self.jitter = max;
+        self
+    }
+
+    /// Let a new run start while the previous one is still going.
+    /// Off by default: runs are awaited one at a time.
+    pub fn allow_overlap(mut self, allow: bool) -> Self {
+        self.allow_overlap = allow;
+        self
+    }
+
+    /// Start the job. Dropping the returned handle cancels it.
+    pub fn spawn<F, Fut>(self, task: F) -> JobHandle
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let handle = tokio::spawn(self.run(task, cancel_rx));
+        JobHandle {
+            cancel: Some(cancel_tx),
+            handle,
+        }
+    }
+
+    async fn run<F, Fut>(self, mut task: F, mut cancel: oneshot::Receiver<()>)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let clock = Clock::now();
+        let mut in_flight = JoinSet::new();
+        let mut next = self.first_tick(&clock);
+
+        while let Some(scheduled) = next {
+            tokio::select! {
+                _ = sleep_until(scheduled + random_jitter(self.jitter)) => {}
+                _ = &mut cancel => break,
+            }
+
+            if self.allow_overlap {
+                in_flight.spawn(task());
+                while in_flight.try_join_next().is_some() {}
+            } else {
+                task().await;
+            }
+
+            next = self.next_tick(scheduled, Instant::now(), &clock);
+        }
+
+        // Cancellation stops new runs; in-flight ones finish
+        while in_flight.join_next().await.is_some() {}
+    }
+
+    fn first_tick(&self, clock: &Clock) -> Option<Instant> {
+        let now = Instant::now();
+        match &self.schedule {
+            Schedule::FixedRate(_) | Schedule::FixedDelay(_) => Some(now),
+            Schedule::Cron(expr) => expr.next_after(clock.wall_secs(now)).map(|t| clock.instant(t)),
+        }
+    }
+
+    /// Next start time, given when the last run was scheduled and when it ended.
+    fn next_tick(&self, scheduled: Instant, now: Instant, clock: &Clock) -> Option<Instant> {
+        match &self.schedule {
+            Schedule::FixedDelay(delay) => Some(now + *delay),
+            Schedule::FixedRate(period) => {
+                let next = scheduled + *period;
+                if next >= now {
+                    return Some(next);
+                }
+                Some(match self.missed_tick {
+                    MissedTick::Burst => next,
+                    MissedTick::Delay => now,
+                    MissedTick::Skip => {
+                        let behind = (now - scheduled).as_nanos();
+                        let periods = behind.div_ceil(period.as_nanos()) as u32;
+                        scheduled + *period * periods
+                    }
+                })
+            }
+            Schedule::Cron(expr) => {
+                let now_secs = clock.wall_secs(now);
+                let next = expr.next_after(clock.wall_secs(scheduled))?;
+                if next >= now_secs {
+                    return Some(clock.instant(next));
+                }
+                match self.missed_tick {
+                    MissedTick::Burst => Some(clock.instant(next)),
+                    MissedTick::Delay => Some(now),
+                    MissedTick::Skip => expr.next_after(now_secs).map(|t| clock.instant(t)),
+                }
+            }
+        }
+    }
+}
+
+pub struct JobHandle {
+    cancel: Option<oneshot::Sender<()>>,
+    handle: JoinHandle<()>,
+}
+
+impl JobHandle {
+    /// Stop scheduling new runs and wait for in-flight runs to finish.
+    pub async fn cancel(mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            let _ = cancel.send(());
+        }
+        let _ = self.handle.await;
+    }
+}
+
+fn random_jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    // RandomState is seeded per instance; good enough to spread load
+    let random = RandomState::new().hash_one(Instant::now());
+    Duration::from_nanos(random % max.as_nanos() as u64)
+}
+
+/// Maps tokio instants to wall-clock time (so paused test time works).
+struct Clock {
+    instant: Instant,
+    wall: Duration,
+}
+
+impl Clock {
+    fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            wall: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(),
+        }
+    }
+
+    fn wall_secs(&self, at: Instant) -> u64 {
+        (self.wall + (at - self.instant)).as_secs()
+    }
+
+    fn instant(&self, wall_secs: u64) -> Instant {
+        let offset = Duration::from_secs(wall_secs).saturating_sub(self.wall);
+        self.instant + offset
+    }
+}
+
+// =====================================================
+// Cron Expressions
+// =====================================================
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CronError {
+    #[error("expected 5 fields, got {0}")]
+    FieldCount(usize),
+
+    #[error("invalid {field} field: {value:?}")]
+    InvalidField { field: &'static str, value: String },
+}
+
+/// Parsed cron expression; each field is a bitset of allowed values.
+///
+/// Supports `*`, `a`, `a-b`, lists with `,` and steps with `/n`.
+/// As in classic cron, when both day-of-month and day-of-week are
+/// restricted a day matching either one fires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+impl std::str::FromStr for CronExpr {
+    type Err = CronError;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, dom, month, dow] = fields[..] else {
+            return Err(CronError::FieldCount(fields.len()));
+        };
+
+        // Accept 7 as an alias for Sunday
+        let mut days_of_week = parse_field(dow, "day-of-week", 0, 7)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_field(minute, "minute", 0, 59)?,
+            hours: parse_field(hour, "hour", 0, 23)?,
+            days_of_month: parse_field(dom, "day-of-month", 1, 31)?,
+            months: parse_field(month, "month", 1, 12)?,
+            days_of_week,
+            dom_restricted: dom != "*",
+            dow_restricted: dow != "*",
+        })
+    }
+}
+
+fn parse_field(field: &str, name: &'static str, min: u32, max: u32) -> Result<u64, CronError> {
+    let invalid = || CronError::InvalidField {
+        field: name,
+        value: field.to_string(),
+    };
+    let number = |s: &str| -> Result<u32, CronError> {
+        s.parse::<u32>()
+            .ok()
+            .filter(|n| (min..=max).contains(n))
+            .ok_or_else(invalid)
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step.parse::<u32>().ok().filter(|&s| s > 0).ok_or_else(invalid)?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (number(a)?, number(b)?),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if start > end {
+            return Err(invalid());
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl CronExpr {
+    /// First matching minute strictly after `unix_secs`, or `None` if the
+    /// expression never fires (e.g. February 30th).
+    pub fn next_after(&self, unix_secs: u64) -> Option<u64> {
+        let start = (unix_secs / 60 + 1) * 60;
+        let first_day = start / 86_400;
+        let first_minute = (start % 86_400) / 60;
+
+        // Eight years covers every leap-day-only expression
+        for day in first_day..first_day + 366 * 8 {
+            if !self.matches_day(day) {
+                continue;
+            }
+            let from = if day == first_day { first_minute } else { 0 };
+            for minute_of_day in from..24 * 60 {
+                let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+                if self.hours & (1 << hour) != 0 && self.minutes & (1 << minute) != 0 {
+                    return Some(day * 86_400 + minute_of_day * 60);
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, days_since_epoch: u64) -> bool {
+        let (_, month, dom) = civil_from_days(days_since_epoch as i64);
+        // 1970-01-01 was a Thursday
+        let dow = (days_since_epoch + 4) % 7;
+
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        let dom_ok = self.days_of_month & (1 << dom) != 0;
+        let dow_ok = self.days_of_week & (1 << dow) != 0;
+        if self.dom_restricted && self.dow_restricted {
+            dom_ok || dow_ok
+        } else {
+            dom_ok && dow_ok
+        }
+    }
+}
+
+/// Days since 1970-01-01 to (year, month, day), proleptic Gregorian
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// =====================================================
+// Main
+// =====================================================
+
+#[tokio::main]
+async fn main() -> Result<(), CronError> {
+    let heartbeat = Job::fixed_rate(Duration::from_millis(200))
+        .jitter(Duration::from_millis(20))
+        .spawn(|| async { println!("heartbeat") });
+
+    let cleanup = Job::fixed_delay(Duration::from_millis(300)).spawn(|| async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        println!("cleanup done");
+    });
+
+    let report = Job::cron("*/5 * * * *")?
+        .missed_tick(MissedTick::Skip)
+        .spawn(|| async { println!("report") });
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    heartbeat.cancel().await;
+    cleanup.cancel().await;
+    report.cancel().await;
+    Ok(())
+}
+
+// =====================================================
+// Tests
+// =====================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Run a job for `total`, recording each run's start offset in ms.
+    /// The first run takes `first_run`, the rest are instant.
+    async fn record(job: Job, first_run: Duration, total: Duration) -> Vec<u64> {
+        let start = Instant::now();
+        let starts = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = Arc::clone(&starts);
+        let handle = job.spawn(move || {
+            let starts = Arc::clone(&recorded);
+            async move {
+                let is_first = {
+                    let mut starts = starts.lock().unwrap();
+                    starts.push(start.elapsed().as_millis() as u64);
+                    starts.len() == 1
+                };
+                if is_first {
+                    tokio::time::sleep(first_run).await;
+                }
+            }
+        });
+
+        tokio::time::sleep(total).await;
+        handle.cancel().await;
+        let starts = starts.lock().unwrap().clone();
+        starts
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_missed_tick_policies() {
+        let slow = Duration::from_millis(250);
+        let total = Duration::from_millis(420);
+        let job = |policy| Job::fixed_rate(Duration::from_millis(100)).missed_tick(policy);
+
+        assert_eq!(record(job(MissedTick::Skip), slow, total).await, vec![0, 300, 400]);
+        assert_eq!(record(job(MissedTick::Burst), slow, total).await, vec![0, 250, 250, 300, 400]);
+        assert_eq!(record(job(MissedTick::Delay), slow, total).await, vec![0, 250, 350]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fixed_delay_waits_after_run() {
+        let job = Job::fixed_delay(Duration::from_millis(100));
+        let starts = record(job, Duration::from_millis(50), Duration::from_millis(300)).await;
+        assert_eq!(starts, vec![0, 150, 250]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_overlap_prevention() {
+        let running = Arc::new(Mutex::new((0u32, 0u32))); // (current, max)
+
+        for allow in [false, true] {
+            *running.lock().unwrap() = (0, 0);
+            let counter = Arc::clone(&running);
+            let handle = Job::fixed_rate(Duration::from_millis(100))
+                .allow_overlap(allow)
+                .spawn(move || {
+                    let counter = Arc::clone(&counter);
+                    async move {
+                        {
+                            let mut c = counter.lock().unwrap();
+                            c.0 += 1;
+                            c.1 = c.1.max(c.0);
+                        }
+                        tokio::time::sleep(Duration::from_millis(250)).await;
+                        counter.lock().unwrap().0 -= 1;
+                    }
+                });
+
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            handle.cancel().await;
+
+            let (current, max) = *running.lock().unwrap();
+            assert_eq!(current, 0, "cancel waits for in-flight runs");
+            assert_eq!(max > 1, allow);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_jitter_stays_within_bound() {
+        let job = Job::fixed_rate(Duration::from_millis(100)).jitter(Duration::from_millis(30));
+        let starts = record(job, Duration::ZERO, Duration::from_millis(390)).await;
+
+        assert_eq!(starts.len(), 4);
+        for (i, start) in starts.into_iter().enumerate() {
+            let tick = i as u64 * 100;
+            assert!((tick..tick + 30).contains(&start), "run {} at {}ms", i, start);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancel_stops_runs() {
+        let count = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&count);
+        let handle = Job::fixed_rate(Duration::from_millis(100)).spawn(move || {
+            let counter = Arc::clone(&counter);
+            async move { *counter.lock().unwrap() += 1 }
+        });
+
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        handle.cancel().await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(*count.lock().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        // 2024-01-01 00:00 UTC, a Monday
+        let monday = 1_704_067_200;
+        let business = Job::cron("*/15 9-17 * * 1-5").unwrap();
+        let Schedule::Cron(business) = business.schedule else { unreachable!() };
+
+        assert_eq!(business.next_after(monday), Some(monday + 9 * 3600));
+        assert_eq!(business.next_after(monday + 9 * 3600), Some(monday + 9 * 3600 + 900));
+
+        // Friday 17:45 -> Monday 09:00
+        assert_eq!(business.next_after(1_704_476_700), Some(1_704_704_400));
+
+        // Day-of-month OR day-of-week: first Friday beats the 13th
+        let friday_13: CronExpr = "0 0 13 * 5".parse().unwrap();
+        assert_eq!(friday_13.next_after(1_725_148_800), Some(1_725_580_800));
+
+        // Leap day after 2024-03-01 is 2028-02-29
+        let leap: CronExpr = "0 0 29 2 *".parse().unwrap();
+        assert_eq!(leap.next_after(1_709_251_200), Some(1_835_395_200));
+
+        let never: CronExpr = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(never.next_after(monday), None);
+    }
+
+    #[test]
+    fn test_cron_parse_errors() {
+        assert_eq!("* * * *".parse::<CronExpr>(), Err(CronError::FieldCount(4)));
+        assert!(matches!(
+            "60 * * * *".parse::<CronExpr>(),
+            Err(CronError::InvalidField { field: "minute", .. })
+        ));
+        assert!("*/0 * * * *".parse::<CronExpr>().is_err());
+        assert!("* 5-1 * * *".parse::<CronExpr>().is_err());
+        assert_eq!(
+            "0 0 * * 7".parse::<CronExpr>().unwrap(),
+            "0 0 * * 0".parse::<CronExpr>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,392 @@
+//! Single-flight request coalescing and an async memoizing cache
+//!
+//! When many tasks ask for the same key at once, only the first one does the
+//! work; the others wait and receive a clone of its result, errors included.
+//!
+//! Add to Cargo.toml:
+//! ```toml
+//! [dependencies]
+//! tokio = { version = "1", features = ["full"] }
+//!
+//! [dev-dependencies]
+//! tokio = { version = "1", features = ["full", "test-util"] }
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tokio::time::Instant;
+
+// =====================================================
+// Single-Flight Group
+// =====================================================
+
+type Call<V, E> = Arc<OnceCell<Result<V, E>>>;
+
+/// Dedupes concurrent calls per key.
+///
+/// `V` and `E` are cloned out to every waiter; wrap them in `Arc` if they are
+/// expensive or not `Clone` (e.g. `Arc<io::Error>`).
+pub struct Group<K, V, E> {
+    calls: Mutex<HashMap<K, Call<V, E>>>,
+}
+
+impl<K, V, E> Group<K, V, E>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    E: Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run `f` for `key` unless a call for the same key is already in
+    /// flight, in which case wait for that one and share its result.
+    ///
+    /// If the task running `f` is cancelled, one of the waiters takes over
+    /// and runs its own `f`.
+    pub async fn work<F, Fut>(&self, key: K, f: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let call = {
+            let mut calls = self.calls.lock().unwrap();
+            Arc::clone(calls.entry(key.clone()).or_default())
+        };
+        // Also runs if this future is dropped mid-call
+        let forget = Forget {
+            calls: &self.calls,
+            key,
+            call: Some(call),
+        };
+
+        let result = forget.call().get_or_init(f).await.clone();
+        result
+    }
+
+    /// Number of keys with a call in flight
+    pub fn in_flight(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+}
+
+/// Removes a call from the map when `work` returns or is cancelled.
+struct Forget<'a, K: Eq + Hash, V, E> {
+    calls: &'a Mutex<HashMap<K, Call<V, E>>>,
+    key: K,
+    // Only None while dropping
+    call: Option<Call<V, E>>,
+}
+
+impl<K: Eq + Hash, V, E> Forget<'_, K, V, E> {
+    fn call(&self) -> &Call<V, E> {
+        self.call.as_ref().expect("present until drop")
+    }
+}
+
+impl<K: Eq + Hash, V, E> Drop for Forget<'_, K, V, E> {
+    fn drop(&mut self) {
+        let mut calls = self.calls.lock().unwrap_or_else(PoisonError::into_inner);
+        let call = self.call.take().expect("present until drop");
+
+        // Forget a finished call so the next request runs fresh. A cancelled
+        // one stays for a waiter to take over, unless only the map is left
+        let finished = call.initialized() || Arc::strong_count(&call) == 2;
+        let current = calls.get(&self.key).is_some_and(|c| Arc::ptr_eq(c, &call));
+        if finished && current {
+            calls.remove(&self.key);
+        }
+
+        // Released under the lock, so the next caller to get here sees an
+        // exact count
+        drop(call);
+    }
+}
+
+impl<K, V, E> Default for Group<K, V, E>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    E: Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// =====================================================
+// Memoizing Cache with TTL
+// =====================================================
+
+struct Entry<V> {
+    value: V,
+    expires_at: Instant,
+}
+
+/// Caches successful loads for `ttl`; concurrent misses share one load.
+/// Errors are shared with concurrent waiters but never cached.
+pub struct Cache<K, V, E> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, Entry<V>>>,
+    loads: Group<K, V, E>,
+}
+
+impl<K, V, E> Cache<K, V, E>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    E: Clone,
+{
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            loads: Group::new(),
+        }
+    }
+
+    /// Cached value for `key`, or the result of `load` on a miss.
+    pub async fn get_or_load<F, Fut>(&self, key: K, load: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+
+        let cache_key = key.clone();
+        self.loads
+            .work(key, || async move {
+                let result = load().await;
+                if let Ok(value) = &result {
+                    self.entries.lock().unwrap().insert(
+                        cache_key,
+                        Entry {
+                            value: value.clone(),
+                            expires_at: Instant::now() + self.ttl,
+                        },
+                    );
+                }
+                result
+            })
+            .await
+    }
+
+    /// Fresh cached value, if any. Expired entries are removed.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => Some(entry.value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn invalidate(&self, key: &K) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+// =====================================================
+// Example: Deduplicated Fan-Out Fetch
+// =====================================================
+
+async fn fetch(url: String) -> Result<String, String> {
+    println!("Fetching {}", url);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    Ok(format!("Response from {}", url))
+}
+
+async fn parallel_fetch_deduped(urls: Vec<String>) -> Vec<String> {
+    let cache = Arc::new(Cache::new(Duration::from_secs(60)));
+    let mut set = tokio::task::JoinSet::new();
+
+    for url in urls {
+        let cache = Arc::clone(&cache);
+        set.spawn(async move { cache.get_or_load(url.clone(), || fetch(url)).await });
+    }
+
+    let mut results = Vec::new();
+    while let Some(res) = set.join_next().await {
+        if let Ok(Ok(data)) = res {
+            results.push(data);
+        }
+    }
+    results
+}
+
+// =====================================================
+// Main
+// =====================================================
+
+#[tokio::main]
+async fn main() {
+    let urls = vec![
+        "http://a.com".to_string(),
+        "http://a.com".to_string(),
+        "http://b.com".to_string(),
+        "http://a.com".to_string(),
+    ];
+    // "Fetching http://a.com" is printed once
+    let results = parallel_fetch_deduped(urls).await;
+    println!("Fetched: {:?}", results);
+}
+
+// =====================================================
+// Tests
+// =====================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn slow_load(calls: &AtomicUsize, result: Result<u32, String>) -> Result<u32, String> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        result
+    }
+
+    async fn concurrent<F, Fut>(n: usize, f: F) -> Vec<Result<u32, String>>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<u32, String>> + Send + 'static,
+    {
+        let mut set = tokio::task::JoinSet::new();
+        for _ in 0..n {
+            set.spawn(f());
+        }
+        set.join_all().await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_calls_share_one_execution() {
+        let group = Arc::new(Group::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let results = concurrent(10, || {
+            let (group, calls) = (Arc::clone(&group), Arc::clone(&calls));
+            async move { group.work("key", || slow_load(&calls, Ok(7))).await }
+        })
+        .await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|r| *r == Ok(7)));
+        assert_eq!(group.in_flight(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_errors_are_shared_not_remembered() {
+        let group = Arc::new(Group::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let results = concurrent(5, || {
+            let (group, calls) = (Arc::clone(&group), Arc::clone(&calls));
+            async move { group.work("key", || slow_load(&calls, Err("down".into()))).await }
+        })
+        .await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|r| *r == Err("down".to_string())));
+
+        // Finished calls are forgotten: the next request retries
+        assert_eq!(group.work("key", || slow_load(&calls, Ok(1))).await, Ok(1));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_distinct_keys_run_independently() {
+        let group = Group::new();
+        let calls = AtomicUsize::new(0);
+
+        let (a, b) = tokio::join!(
+            group.work("a", || slow_load(&calls, Ok(1))),
+            group.work("b", || slow_load(&calls, Ok(2))),
+        );
+
+        assert_eq!((a, b), (Ok(1), Ok(2)));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_waiter_takes_over_cancelled_leader() {
+        let group = Arc::new(Group::<&str, u32, String>::new());
+
+        let leader = {
+            let group = Arc::clone(&group);
+            tokio::spawn(async move {
+                group
+                    .work("key", || async {
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                        Ok(1)
+                    })
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let follower = {
+            let group = Arc::clone(&group);
+            tokio::spawn(async move { group.work("key", || async { Ok(2) }).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        leader.abort();
+
+        assert_eq!(follower.await.unwrap(), Ok(2));
+        assert_eq!(group.in_flight(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_call_is_forgotten() {
+        let group = Group::<&str, u32, String>::new();
+
+        let call = group.work("key", || async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(1)
+        });
+        let timed_out = tokio::time::timeout(Duration::from_millis(10), call).await;
+
+        // Nobody was left to take over, so nothing stays behind
+        assert!(timed_out.is_err());
+        assert_eq!(group.in_flight(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cache_expires_after_ttl() {
+        let cache = Cache::new(Duration::from_secs(30));
+        let calls = AtomicUsize::new(0);
+
+        assert_eq!(cache.get_or_load("k", || slow_load(&calls, Ok(1))).await, Ok(1));
+        assert_eq!(cache.get_or_load("k", || slow_load(&calls, Ok(2))).await, Ok(1));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::advance(Duration::from_secs(31)).await;
+        assert_eq!(cache.get(&"k"), None);
+        assert_eq!(cache.get_or_load("k", || slow_load(&calls, Ok(2))).await, Ok(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cache_does_not_store_errors() {
+        let cache = Cache::new(Duration::from_secs(30));
+        let calls = AtomicUsize::new(0);
+
+        let failed = cache.get_or_load("k", || slow_load(&calls, Err("down".into()))).await;
+        assert!(failed.is_err());
+        assert_eq!(cache.get_or_load("k", || slow_load(&calls, Ok(3))).await, Ok(3));
+
+        cache.invalidate(&"k");
+        assert_eq!(cache.get(&"k"), None);
+    }
+}
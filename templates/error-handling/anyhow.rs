@@ -1,41 +1,78 @@
 //! Error handling template for applications using anyhow
 //!
+//! A config error that carries its location (a library `Diagnostic`) is
+//! reported with the offending line. The process exits with a sysexits.h
+//! code (usage, config, I/O, unavailable, internal) chosen from the error
+//! chain; tests/exit-codes.rs runs the binary, named `app`, to check them.
+//!
+//! Context can carry key/value fields (`user_id=42`) that stay queryable
+//! on the final error; set LOG_FORMAT=json to have `main` print the error
+//! as a JSON log line with those fields.
+//!
+//! With the `trace` feature, the error report printed by `main` ends with
+//! the backtrace (set RUST_BACKTRACE=1) and the tracing spans the error
+//! occurred in.
+//!
 //! Add to Cargo.toml:
 //! ```toml
 //! [dependencies]
 //! anyhow = "1"
+//! serde = { version = "1", features = ["derive"] }
+//! serde_json = "1"
+//! tracing = { version = "0.1", optional = true }
+//! tracing-error = { version = "0.2", optional = true }
+//! tracing-subscriber = { version = "0.3", optional = true }
+//!
+//! [features]
+//! trace = ["dep:tracing", "dep:tracing-error", "dep:tracing-subscriber"]
 //! ```
 
 use anyhow::{Context, Result, bail, ensure};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::process::{ExitCode, Termination};
+use std::time::Duration;
 
 // =====================================================
 // Main Application Pattern
 // =====================================================
 
-fn main() -> Result<()> {
+fn main() -> Exit {
+    // Exit prints the error chain and picks the exit code
+    Exit(try_main(std::env::args().skip(1)))
+}
+
+fn try_main(args: impl Iterator<Item = String>) -> Result<()> {
     // Setup (logging, etc.)
     init_logging()?;
 
     // Run main logic
-    if let Err(e) = run() {
-        // Log full error chain
-        eprintln!("Error: {:#}", e);
-
-        // For debugging, show backtrace
-        // RUST_BACKTRACE=1 cargo run
-        std::process::exit(1);
-    }
-
-    Ok(())
+    let args = parse_args(args)?;
+    run(&args)
 }
 
 fn init_logging() -> Result<()> {
+    // ErrorLayer is what lets errors record the spans they occur in
+    #[cfg(feature = "trace")]
+    {
+        use tracing_subscriber::layer::SubscriberExt;
+        use tracing_subscriber::util::SubscriberInitExt;
+
+        tracing_subscriber::registry()
+            .with(tracing_subscriber::fmt::layer())
+            .with(tracing_error::ErrorLayer::default())
+            .try_init()?;
+    }
+
     // Logging setup...
     Ok(())
 }
 
-fn run() -> Result<()> {
-    let config = load_config("config.toml")
+#[cfg_attr(feature = "trace", tracing::instrument)]
+fn run(args: &Args) -> Result<()> {
+    let config = load_config(&args.config)
         .context("failed to load configuration")?;
 
     let db = connect_database(&config.db_url)
@@ -47,18 +84,129 @@ fn run() -> Result<()> {
     Ok(())
 }
 
+// =====================================================
+// Command Line
+// =====================================================
+
+#[derive(Debug)]
+struct Args {
+    config: String,
+}
+
+/// A bad command line.
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (usage: app [--config <path>])", self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut config = "config.toml".to_string();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args.next();
+                config = path.ok_or_else(|| UsageError("`--config` needs a path".into()))?;
+            }
+            _ => Err(UsageError(format!("unexpected argument `{}`", arg)))?,
+        }
+    }
+    Ok(Args { config })
+}
+
+// =====================================================
+// Exit Codes
+// =====================================================
+
+/// Exit codes from BSD sysexits.h, which shells and supervisors recognize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum ExitKind {
+    /// Bad command line (EX_USAGE)
+    Usage = 64,
+    /// A service we depend on is down or throttling us (EX_UNAVAILABLE)
+    Unavailable = 69,
+    /// Nothing more specific applies, so probably a bug (EX_SOFTWARE)
+    Internal = 70,
+    /// A file could not be read or written (EX_IOERR)
+    Io = 74,
+    /// The configuration is invalid (EX_CONFIG)
+    Config = 78,
+}
+
+/// Picks the kind from the whole chain, most specific first: a bad command
+/// line, then I/O (transient failures mean a service is unavailable), then
+/// invalid config.
+fn exit_kind(err: &anyhow::Error) -> ExitKind {
+    if has::<UsageError>(err) {
+        return ExitKind::Usage;
+    }
+    if retry_class(err) != RetryClass::Permanent {
+        return ExitKind::Unavailable;
+    }
+    if has::<io::Error>(err) {
+        return ExitKind::Io;
+    }
+    if has::<ConfigError>(err) || has::<Diagnostic>(err) {
+        return ExitKind::Config;
+    }
+    ExitKind::Internal
+}
+
+/// Whether a `T` is in the chain, either as an error or as context.
+fn has<T: std::error::Error + Send + Sync + 'static>(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<T>().is_some() || err.chain().any(|cause| cause.is::<T>())
+}
+
+/// What `main` returns: prints the error chain once, then exits with the
+/// code for its `ExitKind`.
+struct Exit(Result<()>);
+
+impl Exit {
+    fn code(&self) -> u8 {
+        match &self.0 {
+            Ok(()) => 0,
+            Err(err) => exit_kind(err) as u8,
+        }
+    }
+}
+
+impl Termination for Exit {
+    fn report(self) -> ExitCode {
+        let code = self.code();
+        if let Err(err) = &self.0 {
+            if std::env::var("LOG_FORMAT").as_deref() == Ok("json") {
+                eprintln!("{}", Report(err).to_json());
+            } else {
+                // Full chain, the offending config line and, with the
+                // `trace` feature, backtrace and spans
+                eprintln!("Error: {:#}", Report(err));
+            }
+        }
+        ExitCode::from(code)
+    }
+}
+
 // =====================================================
 // Error Context Pattern
 // =====================================================
 
+#[cfg_attr(feature = "trace", tracing::instrument)]
 fn load_config(path: &str) -> Result<Config> {
     let content = std::fs::read_to_string(path)
-        .with_context(|| format!("reading config file: {}", path))?;
+        .in_span()
+        .with_fields(|| Fields::new("reading config file").with("path", path))?;
 
-    let config: Config = parse_config(&content)
+    let config: Config = parse_config(path, &content)
         .context("parsing config")?;
 
-    validate_config(&config)?;
+    validate_config(&config)
+        .with_context(|| ConfigError(format!("invalid configuration in {}", path)))?;
 
     Ok(config)
 }
@@ -76,6 +224,89 @@ fn validate_config(config: &Config) -> Result<()> {
     Ok(())
 }
 
+// =====================================================
+// Structured Context
+// =====================================================
+
+/// Context with key/value fields, attached with `with_fields` where
+/// `with_context` would take a formatted string.
+///
+/// It is ordinary anyhow context, so `?` and downcasts to the errors below
+/// it work as before. Each layer also holds the fields of the layers below
+/// it, which lets `fields` answer for the whole chain.
+#[derive(Debug)]
+struct Fields {
+    message: String,
+    /// This layer's fields, in the order given; shown by Display
+    own: Vec<(&'static str, String)>,
+    /// This layer's fields and every inner layer's
+    all: BTreeMap<&'static str, String>,
+}
+
+impl Fields {
+    fn new(message: impl Into<String>) -> Self {
+        Fields {
+            message: message.into(),
+            own: Vec::new(),
+            all: BTreeMap::new(),
+        }
+    }
+
+    fn with(mut self, key: &'static str, value: impl fmt::Display) -> Self {
+        let value = value.to_string();
+        self.all.insert(key, value.clone());
+        self.own.push((key, value));
+        self
+    }
+}
+
+impl fmt::Display for Fields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        for (i, (key, value)) in self.own.iter().enumerate() {
+            let sep = if i == 0 { " (" } else { ", " };
+            write!(f, "{}{}={}", sep, key, value)?;
+        }
+        if !self.own.is_empty() {
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+/// `with_context` for `Fields`.
+trait WithFields<T> {
+    fn with_fields(self, fields: impl FnOnce() -> Fields) -> Result<T>;
+}
+
+impl<T, E> WithFields<T> for std::result::Result<T, E>
+where
+    E: Into<anyhow::Error>,
+{
+    fn with_fields(self, fields: impl FnOnce() -> Fields) -> Result<T> {
+        self.map_err(|err| {
+            let err = err.into();
+            let mut fields = fields();
+            // The nearest inner layer already holds everything below it;
+            // on a clash the outer value wins
+            if let Some(inner) = err.downcast_ref::<Fields>() {
+                for (key, value) in &inner.all {
+                    fields.all.entry(key).or_insert_with(|| value.clone());
+                }
+            }
+            err.context(fields)
+        })
+    }
+}
+
+/// Every field attached anywhere in the chain.
+fn fields(err: &anyhow::Error) -> BTreeMap<&'static str, &str> {
+    match err.downcast_ref::<Fields>() {
+        Some(outer) => outer.all.iter().map(|(k, v)| (*k, v.as_str())).collect(),
+        None => BTreeMap::new(),
+    }
+}
+
 // =====================================================
 // Error Propagation Pattern
 // =====================================================
@@ -86,14 +317,15 @@ fn process_data(db: &Database) -> Result<()> {
 
     for user in users {
         process_user(&user)
-            .with_context(|| format!("processing user: {}", user.id))?;
+            .with_fields(|| Fields::new("processing user").with("user_id", user.id))?;
     }
 
     Ok(())
 }
 
 fn process_user(user: &User) -> Result<()> {
-    let data = fetch_user_data(user.id)
+    // Transient failures are retried before context is added
+    let data = with_retries(|| fetch_user_data(user.id))
         .with_context(|| format!("fetching data for user {}", user.id))?;
 
     transform_data(&data)
@@ -122,14 +354,37 @@ fn find_user_email(id: u64) -> Result<String> {
 // Downcast Pattern (when needed)
 // =====================================================
 
-fn handle_error(err: anyhow::Error) {
-    // Try to downcast to specific error type
-    if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+/// Attempts made before a retryable error is returned anyway.
+const MAX_ATTEMPTS: u32 = 4;
+/// Delay after the first failure; doubled after each further one.
+const BASE_DELAY: Duration = Duration::from_millis(100);
+
+/// Decides what to do after attempt number `attempt` (counting from 1)
+/// failed: the delay before the next attempt, or None to give up.
+fn handle_error(err: &anyhow::Error, attempt: u32) -> Option<Duration> {
+    assert!(attempt >= 1, "attempts are numbered from 1");
+
+    // Downcast to the error types we can classify
+    let class = retry_class(err);
+    if class != RetryClass::Permanent && attempt < MAX_ATTEMPTS {
+        let delay = match class {
+            RetryClass::RateLimited { retry_after } => retry_after,
+            _ => BASE_DELAY * 2u32.pow(attempt - 1),
+        };
+        println!(
+            "Attempt {} failed, retrying in {:?}: {:#}",
+            attempt, delay, err
+        );
+        return Some(delay);
+    }
+
+    // Giving up: look through the chain, not just the outermost error
+    if let Some(io_err) = err.chain().find_map(|e| e.downcast_ref::<io::Error>()) {
         match io_err.kind() {
-            std::io::ErrorKind::NotFound => {
+            io::ErrorKind::NotFound => {
                 println!("File not found, using defaults");
             }
-            std::io::ErrorKind::PermissionDenied => {
+            io::ErrorKind::PermissionDenied => {
                 println!("Permission denied");
             }
             _ => {
@@ -139,6 +394,314 @@ fn handle_error(err: anyhow::Error) {
     } else {
         println!("Error: {:#}", err);
     }
+    None
+}
+
+/// Runs `op` until it succeeds or `handle_error` gives up.
+fn with_retries<T>(mut op: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(err) => match handle_error(&err, attempt) {
+                Some(delay) => std::thread::sleep(delay),
+                None => return Err(err),
+            },
+        }
+        attempt += 1;
+    }
+}
+
+// =====================================================
+// Retry Classification
+// =====================================================
+
+// `RetryClass` and `ClassifyRetry` stand in for the ones a library exports
+// (see thiserror.rs); an application imports them rather than defining
+// its own. Each library error type that implements the trait gets one
+// downcast arm in retry_class.
+
+/// Whether the operation that failed is worth retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RetryClass {
+    /// Transient: retry with backoff
+    Retryable,
+    /// Retrying gives the same result
+    Permanent,
+    /// Transient, but not before the given delay
+    RateLimited { retry_after: Duration },
+}
+
+/// Classifies an error for retry decisions.
+trait ClassifyRetry {
+    fn retry_class(&self) -> RetryClass;
+}
+
+impl ClassifyRetry for io::Error {
+    fn retry_class(&self) -> RetryClass {
+        use io::ErrorKind::*;
+        match self.kind() {
+            Interrupted | WouldBlock | TimedOut | ConnectionRefused | ConnectionReset
+            | ConnectionAborted | NotConnected | BrokenPipe => RetryClass::Retryable,
+            _ => RetryClass::Permanent,
+        }
+    }
+}
+
+impl ClassifyRetry for Throttled {
+    fn retry_class(&self) -> RetryClass {
+        RetryClass::RateLimited {
+            retry_after: self.retry_after,
+        }
+    }
+}
+
+/// Classified by the first error in the chain whose type is known.
+fn retry_class(err: &anyhow::Error) -> RetryClass {
+    for cause in err.chain() {
+        if let Some(io_err) = cause.downcast_ref::<io::Error>() {
+            return io_err.retry_class();
+        }
+        if let Some(throttled) = cause.downcast_ref::<Throttled>() {
+            return throttled.retry_class();
+        }
+    }
+    RetryClass::Permanent
+}
+
+// =====================================================
+// Config Parsing
+// =====================================================
+
+// Reading config is usually a library's job: `parse_config` in thiserror.rs
+// is the full version, with column spans and labeled underlines. This one
+// only does enough to show what the application does with the library's
+// `Diagnostic` (see `Report`).
+
+/// Stand-in for the library's `Diagnostic`: a config error on a known line.
+#[derive(Debug)]
+struct Diagnostic {
+    message: String,
+    file: String,
+    line: usize,
+    snippet: String,
+}
+
+impl Diagnostic {
+    /// The offending line under its location.
+    fn render(&self) -> String {
+        format!(
+            "error: {}\n --> {}:{}\n  |\n  | {}\n",
+            self.message, self.file, self.line, self.snippet
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Parses `key = value` lines, skipping blank lines and `#` comments.
+fn parse_config(file: &str, source: &str) -> Result<Config> {
+    let mut port = None;
+    let mut db_url = None;
+    let mut timeout_secs = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let text = line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let invalid = |message: String| Diagnostic {
+            message,
+            file: file.to_string(),
+            line: index + 1,
+            snippet: line.to_string(),
+        };
+
+        let Some((key, value)) = text.split_once('=') else {
+            Err(invalid("expected `key = value`".to_string()))?
+        };
+        let value = value.trim();
+        match key.trim() {
+            "port" => {
+                let parsed = value.parse();
+                port = Some(parsed.map_err(|_| invalid("invalid port".to_string()))?);
+            }
+            "db_url" => db_url = Some(value.trim_matches('"').to_string()),
+            "timeout_secs" => {
+                let parsed = value.parse();
+                timeout_secs = Some(parsed.map_err(|_| invalid("invalid timeout".to_string()))?);
+            }
+            key => Err(invalid(format!("unknown key `{}`", key)))?,
+        }
+    }
+
+    // A missing key has no line to point at
+    let missing = |key| ConfigError(format!("{}: missing key `{}`", file, key));
+    Ok(Config {
+        port: port.ok_or_else(|| missing("port"))?,
+        db_url: db_url.ok_or_else(|| missing("db_url"))?,
+        timeout_secs: timeout_secs.ok_or_else(|| missing("timeout_secs"))?,
+    })
+}
+
+/// Invalid configuration without a location, as an error or as context.
+#[derive(Debug)]
+struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// =====================================================
+// Error Report
+// =====================================================
+
+/// Terminal report for an error.
+///
+/// `{}` is the outermost message and `{:#}` the whole chain on one line,
+/// followed by the offending line if a `Diagnostic` is in the chain.
+/// With the `trace` feature, `{:#}` then adds the backtrace anyhow captured
+/// and the innermost span trace recorded in the chain.
+struct Report<'a>(&'a anyhow::Error);
+
+impl Report<'_> {
+    /// Same as anyhow's `chain()`, except that SpanError, which repeats its
+    /// source's message, is skipped; its spans are listed at the end.
+    fn causes(&self) -> impl Iterator<Item = &(dyn std::error::Error + 'static)> {
+        self.0.chain().filter(|cause| !is_span_error(*cause))
+    }
+
+    /// One JSON log line: the chain and every structured field in it.
+    fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Record<'a> {
+            level: &'static str,
+            message: String,
+            chain: Vec<String>,
+            fields: BTreeMap<&'static str, &'a str>,
+        }
+
+        let record = Record {
+            level: "error",
+            message: self.0.to_string(),
+            chain: self.causes().map(|cause| cause.to_string()).collect(),
+            fields: fields(self.0),
+        };
+        serde_json::to_string(&record).expect("string keys and values")
+    }
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !f.alternate() {
+            return write!(f, "{}", self.0);
+        }
+        for (i, cause) in self.causes().enumerate() {
+            if i > 0 {
+                f.write_str(": ")?;
+            }
+            write!(f, "{}", cause)?;
+        }
+
+        // Show the offending line; the chain above only has its location
+        if let Some(diag) = self.0.chain().find_map(|e| e.downcast_ref::<Diagnostic>()) {
+            write!(f, "\n\n{}", diag.render().trim_end())?;
+        }
+
+        #[cfg(feature = "trace")]
+        {
+            use std::backtrace::BacktraceStatus;
+            use tracing_error::SpanTraceStatus;
+
+            let backtrace = self.0.backtrace();
+            if backtrace.status() == BacktraceStatus::Captured {
+                write!(f, "\n\nBacktrace:\n{}", backtrace)?;
+            }
+
+            // Innermost wins: it is closest to where the error happened
+            let span_trace = self
+                .0
+                .chain()
+                .filter_map(|cause| cause.downcast_ref::<SpanError>())
+                .map(|err| &err.span_trace)
+                .filter(|spans| spans.status() == SpanTraceStatus::CAPTURED)
+                .last();
+            if let Some(spans) = span_trace {
+                write!(f, "\n\nSpan trace:\n{}", spans)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts a library error into anyhow where it first appears.
+///
+/// anyhow captures the backtrace itself; with the `trace` feature this also
+/// records the active spans, which are gone by the time `main` reports.
+trait InSpan<T> {
+    fn in_span(self) -> Result<T>;
+}
+
+impl<T, E> InSpan<T> for std::result::Result<T, E>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    fn in_span(self) -> Result<T> {
+        #[cfg(feature = "trace")]
+        let result = self.map_err(|err| SpanError {
+            source: Box::new(err),
+            span_trace: tracing_error::SpanTrace::capture(),
+        });
+        #[cfg(not(feature = "trace"))]
+        let result = self;
+        Ok(result?)
+    }
+}
+
+#[cfg(feature = "trace")]
+fn is_span_error(cause: &(dyn std::error::Error + 'static)) -> bool {
+    cause.is::<SpanError>()
+}
+
+#[cfg(not(feature = "trace"))]
+fn is_span_error(_: &(dyn std::error::Error + 'static)) -> bool {
+    false
+}
+
+/// An error and the spans active when it happened.
+///
+/// Unlike tracing-error's `TracedError`, the wrapped error is the
+/// `source()`, so walking the chain still finds it for downcasts.
+#[cfg(feature = "trace")]
+#[derive(Debug)]
+struct SpanError {
+    source: Box<dyn std::error::Error + Send + Sync>,
+    span_trace: tracing_error::SpanTrace,
+}
+
+#[cfg(feature = "trace")]
+impl fmt::Display for SpanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.source, f)
+    }
+}
+
+#[cfg(feature = "trace")]
+impl std::error::Error for SpanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.source)
+    }
 }
 
 // =====================================================
@@ -158,15 +721,28 @@ struct User {
 }
 struct Data;
 
-fn parse_config(_content: &str) -> Result<Config> {
-    Ok(Config {
-        port: 8080,
-        db_url: "postgres://localhost/db".to_string(),
-        timeout_secs: 30,
-    })
+/// What an HTTP client error might look like after a 429 response.
+#[derive(Debug)]
+struct Throttled {
+    retry_after: Duration,
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rate limited, retry after {:?}", self.retry_after)
+    }
 }
 
-fn connect_database(_url: &str) -> Result<Database> {
+impl std::error::Error for Throttled {}
+
+fn connect_database(url: &str) -> Result<Database> {
+    // Simulated failures for the exit code tests
+    if url.contains("//unavailable/") {
+        return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+    }
+    if url.contains("//broken/") {
+        bail!("driver returned an unexpected response");
+    }
     Ok(Database)
 }
 
@@ -191,3 +767,186 @@ fn transform_data(_data: &Data) -> Result<()> {
 fn save_result(_data: &Data) -> Result<()> {
     Ok(())
 }
+
+// =====================================================
+// Tests
+// =====================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_formats() {
+        let err = load_config("/nonexistent/config.toml")
+            .context("failed to load configuration")
+            .err()
+            .unwrap();
+
+        assert_eq!(Report(&err).to_string(), "failed to load configuration");
+
+        let report = format!("{:#}", Report(&err));
+        assert!(report.starts_with(
+            "failed to load configuration: reading config file (path=/nonexistent/config.toml): "
+        ));
+        // The OS message appears once, and nothing follows it on the line
+        assert_eq!(report.matches("No such file").count(), 1, "{}", report);
+        assert!(
+            !report.lines().next().unwrap().contains("span"),
+            "{}",
+            report
+        );
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn test_report_includes_span_trace() {
+        use tracing_error::ErrorLayer;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+        let err = tracing::subscriber::with_default(subscriber, || {
+            load_config("/nonexistent/config.toml").err().unwrap()
+        });
+
+        let report = format!("{:#}", Report(&err));
+        let spans = report.split("Span trace:").nth(1).expect(&report);
+        assert!(spans.contains("load_config"), "{}", report);
+        assert!(spans.contains("/nonexistent/config.toml"), "{}", report);
+    }
+
+    #[test]
+    fn test_retry_decisions() {
+        let timeout =
+            anyhow::Error::new(io::Error::from(io::ErrorKind::TimedOut)).context("fetching data");
+        assert_eq!(retry_class(&timeout), RetryClass::Retryable);
+        assert_eq!(handle_error(&timeout, 1), Some(BASE_DELAY));
+        assert_eq!(handle_error(&timeout, 3), Some(BASE_DELAY * 4));
+        assert_eq!(handle_error(&timeout, MAX_ATTEMPTS), None);
+
+        let missing = load_config("/nonexistent/config.toml").err().unwrap();
+        assert_eq!(retry_class(&missing), RetryClass::Permanent);
+        assert_eq!(handle_error(&missing, 1), None);
+
+        let throttled = anyhow::Error::new(Throttled {
+            retry_after: Duration::from_millis(5),
+        });
+        assert_eq!(handle_error(&throttled, 1), Some(Duration::from_millis(5)));
+
+        assert_eq!(retry_class(&anyhow::anyhow!("bad")), RetryClass::Permanent);
+    }
+
+    #[test]
+    fn test_with_retries() {
+        let mut calls = 0;
+        let value = with_retries(|| {
+            calls += 1;
+            if calls < 3 {
+                Err(Throttled {
+                    retry_after: Duration::from_millis(1),
+                })?;
+            }
+            Ok(calls)
+        })
+        .unwrap();
+        assert_eq!(value, 3);
+
+        let mut calls = 0;
+        let err = with_retries(|| -> Result<()> {
+            calls += 1;
+            bail!("permanent")
+        })
+        .unwrap_err();
+        assert_eq!(calls, 1);
+        assert_eq!(err.to_string(), "permanent");
+    }
+
+    #[test]
+    fn test_parse_config_fixtures() {
+        let config = parse_config("valid.conf", include_str!("fixtures/valid.conf")).unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.db_url, "postgres://localhost/db");
+
+        let source = include_str!("fixtures/missing-equals.conf");
+        let err = parse_config("missing-equals.conf", source).err().unwrap();
+        let diag = err.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(diag.line, 2);
+        assert_eq!(diag.snippet, r#"db_url "postgres://localhost/db""#);
+
+        let source = include_str!("fixtures/missing-key.conf");
+        let err = parse_config("missing-key.conf", source).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "missing-key.conf: missing key `timeout_secs`"
+        );
+    }
+
+    #[test]
+    fn test_report_renders_diagnostic() {
+        let source = include_str!("fixtures/bad-port.conf");
+        let err = parse_config("bad-port.conf", source)
+            .context("parsing config")
+            .err()
+            .unwrap();
+
+        let report = format!("{:#}", Report(&err));
+        assert!(
+            report.starts_with(
+                "\
+parsing config: bad-port.conf:1: invalid port
+
+error: invalid port
+ --> bad-port.conf:1
+  |
+  | port = 80x80"
+            ),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn test_fields_survive_chain() {
+        fn read(path: &str) -> Result<()> {
+            Err(io::Error::from(io::ErrorKind::NotFound))
+                .with_fields(|| Fields::new("reading").with("path", path))?;
+            Ok(())
+        }
+        let err = read("/srv/users/42.json")
+            .context("loading profile")
+            .with_fields(|| Fields::new("processing user").with("user_id", 42))
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            format!("{:#}", err),
+            "processing user (user_id=42): loading profile: reading (path=/srv/users/42.json): entity not found"
+        );
+        let fields = fields(&err);
+        assert_eq!(fields["user_id"], "42");
+        assert_eq!(fields["path"], "/srv/users/42.json");
+
+        // Fields are plain context, so the cause is still reachable
+        assert!(err.downcast_ref::<io::Error>().is_some());
+        assert_eq!(exit_kind(&err), ExitKind::Io);
+
+        assert_eq!(
+            Report(&err).to_json(),
+            r#"{"level":"error","message":"processing user (user_id=42)","chain":["processing user (user_id=42)","loading profile","reading (path=/srv/users/42.json)","entity not found"],"fields":{"path":"/srv/users/42.json","user_id":"42"}}"#
+        );
+    }
+
+    #[test]
+    fn test_outer_field_wins() {
+        let err = Err::<(), _>(anyhow::anyhow!("failed"))
+            .with_fields(|| Fields::new("inner").with("attempt", 1).with("id", 7))
+            .with_fields(|| Fields::new("outer").with("attempt", 2))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "outer (attempt=2)");
+        assert_eq!(
+            fields(&err),
+            BTreeMap::from([("attempt", "2"), ("id", "7")])
+        );
+        assert!(fields(&anyhow::anyhow!("plain")).is_empty());
+    }
+}
@@ -0,0 +1,378 @@
+//! Error type template written by hand, without derive macros
+//!
+//! The same `Error` as thiserror.rs, with every impl spelled out. Use it
+//! when a dependency-free crate is required, or as a reference for what
+//! `#[derive(thiserror::Error)]` generates.
+//!
+//! The tests build thiserror.rs, kept next to this file, and check these
+//! impls against its derived ones. Add to Cargo.toml for them:
+//! ```toml
+//! [dev-dependencies]
+//! thiserror = "1"
+//! serde = { version = "1", features = ["derive"] }
+//! serde_json = "1"
+//!
+//! # thiserror.rs tests for its optional features, which stay off here
+//! [lints.rust]
+//! unexpected_cfgs = { level = "warn", check-cfg = ['cfg(feature, values("axum", "trace"))'] }
+//! ```
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::num::ParseIntError;
+use std::time::Duration;
+
+/// Main error type for the library
+#[derive(Debug)]
+pub enum Error {
+    /// Configuration error
+    Config { message: String },
+
+    /// Resource not found
+    NotFound { resource_type: String, id: String },
+
+    /// Invalid input
+    InvalidInput(String),
+
+    /// IO error with context
+    Io { context: String, source: io::Error },
+
+    /// Parse error
+    Parse(ParseIntError),
+
+    /// Wrapped external error
+    External(ExternalError),
+
+    /// Invalid input file, with the location of the problem
+    Diagnostic(Box<Diagnostic>),
+}
+
+/// Example external error that can be wrapped
+///
+/// Built with `new` so that fields can be added without breaking callers.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ExternalError {
+    pub message: String,
+    /// Set when the service asked us to back off (e.g. HTTP 429 Retry-After)
+    pub retry_after: Option<Duration>,
+}
+
+impl ExternalError {
+    pub fn new(message: impl Into<String>) -> Self {
+        ExternalError {
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    /// The service asked us to wait `delay` before trying again.
+    pub fn with_retry_after(mut self, delay: Duration) -> Self {
+        self.retry_after = Some(delay);
+        self
+    }
+}
+
+/// Location in a file: 1-based line and column, length in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+/// An error pinned to a span of an input file.
+///
+/// Only the data and its Display; building one from a source range and
+/// rendering the offending line are in thiserror.rs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub file: String,
+    pub span: Span,
+    /// Printed under the span: what was expected there
+    pub label: String,
+    /// The line the span is on, without its line ending
+    pub snippet: String,
+}
+
+/// Result alias for convenience
+pub type Result<T> = std::result::Result<T, Error>;
+
+// =====================================================
+// Display
+// =====================================================
+
+// The message describes this level only; causes are reached via source(),
+// so reporters that walk the chain do not print them twice.
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config { message } => write!(f, "configuration error: {}", message),
+            Error::NotFound { resource_type, id } => {
+                write!(f, "resource not found: {}/{}", resource_type, id)
+            }
+            Error::InvalidInput(msg) => write!(f, "invalid input: {}", msg),
+            Error::Io { context, .. } => write!(f, "IO error: {}", context),
+            Error::Parse(_) => f.write_str("parse error"),
+            // Transparent: forward the formatter so flags like {:#} still apply
+            Error::External(err) => fmt::Display::fmt(err, f),
+            Error::Diagnostic(diag) => fmt::Display::fmt(diag, f),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+impl fmt::Display for ExternalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "external service error: {}", self.message)
+    }
+}
+
+// =====================================================
+// Error (source chain)
+// =====================================================
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Config { .. } | Error::NotFound { .. } | Error::InvalidInput(_) => None,
+            Error::Io { source, .. } => Some(source),
+            Error::Parse(err) => Some(err),
+            // Transparent: the wrapper is invisible, so skip straight to the
+            // wrapped error's own cause
+            Error::External(err) => err.source(),
+            Error::Diagnostic(diag) => diag.source(),
+        }
+    }
+}
+
+impl StdError for ExternalError {}
+
+impl StdError for Diagnostic {}
+
+// =====================================================
+// Conversions
+// =====================================================
+
+// Only variants whose payload identifies them get From; Io needs a context
+// string, so it is built explicitly with map_err.
+
+impl From<ParseIntError> for Error {
+    fn from(err: ParseIntError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+impl From<ExternalError> for Error {
+    fn from(err: ExternalError) -> Self {
+        Error::External(err)
+    }
+}
+
+// Boxed to keep `Result<T, Error>` small; the conversion hides that
+impl From<Diagnostic> for Error {
+    fn from(diag: Diagnostic) -> Self {
+        Error::Diagnostic(Box::new(diag))
+    }
+}
+
+// =====================================================
+// Usage Examples
+// =====================================================
+
+pub fn load_config(path: &str) -> Result<Config> {
+    let content = std::fs::read_to_string(path).map_err(|e| Error::Io {
+        context: format!("reading config from {}", path),
+        source: e,
+    })?;
+
+    // ? converts through the From impl above
+    let _value: i32 = content.trim().parse()?;
+
+    Ok(Config { /* ... */ })
+}
+
+pub fn get_user(id: &str) -> Result<User> {
+    // Simulate not found
+    if id == "unknown" {
+        return Err(Error::NotFound {
+            resource_type: "user".to_string(),
+            id: id.to_string(),
+        });
+    }
+    Ok(User { /* ... */ })
+}
+
+pub fn validate_input(input: &str) -> Result<()> {
+    if input.is_empty() {
+        return Err(Error::InvalidInput("input cannot be empty".to_string()));
+    }
+    Ok(())
+}
+
+// Placeholder types for examples
+pub struct Config;
+pub struct User;
+
+// =====================================================
+// Tests
+// =====================================================
+
+/// The derived `Error` the manual impls must match. Its own tests run here
+/// too.
+#[cfg(test)]
+#[path = "thiserror.rs"]
+#[allow(dead_code)]
+mod derived;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Display of the error followed by every source, outermost first.
+    fn chain(err: &dyn StdError) -> Vec<String> {
+        let mut out = vec![err.to_string()];
+        let mut next = err.source();
+        while let Some(cause) = next {
+            out.push(cause.to_string());
+            next = cause.source();
+        }
+        out
+    }
+
+    fn parse_error() -> ParseIntError {
+        "x".parse::<i32>().unwrap_err()
+    }
+
+    fn io_error() -> io::Error {
+        io::Error::new(io::ErrorKind::NotFound, "file missing")
+    }
+
+    fn diagnostic() -> Diagnostic {
+        Diagnostic {
+            message: "invalid port".into(),
+            file: "service.conf".into(),
+            span: Span {
+                line: 1,
+                column: 8,
+                len: 5,
+            },
+            label: "expected an integer from 0 to 65535".into(),
+            snippet: "port = 80x80".into(),
+        }
+    }
+
+    /// One value of every variant, built for both implementations.
+    fn pairs() -> Vec<(Error, derived::Error)> {
+        vec![
+            (
+                Error::Config {
+                    message: "missing port".into(),
+                },
+                derived::Error::Config {
+                    message: "missing port".into(),
+                },
+            ),
+            (
+                Error::NotFound {
+                    resource_type: "user".into(),
+                    id: "123".into(),
+                },
+                derived::Error::NotFound {
+                    resource_type: "user".into(),
+                    id: "123".into(),
+                },
+            ),
+            (
+                Error::InvalidInput("input cannot be empty".into()),
+                derived::Error::InvalidInput("input cannot be empty".into()),
+            ),
+            (
+                Error::Io {
+                    context: "loading data".into(),
+                    source: io_error(),
+                },
+                derived::Error::Io {
+                    context: "loading data".into(),
+                    source: io_error(),
+                },
+            ),
+            (parse_error().into(), parse_error().into()),
+            (
+                ExternalError::new("timeout").into(),
+                derived::ExternalError::new("timeout").into(),
+            ),
+            (diagnostic().into(), {
+                let Diagnostic {
+                    message,
+                    file,
+                    span,
+                    label,
+                    snippet,
+                } = diagnostic();
+                let span = derived::Span {
+                    line: span.line,
+                    column: span.column,
+                    len: span.len,
+                };
+                derived::Diagnostic {
+                    message,
+                    file,
+                    span,
+                    label,
+                    snippet,
+                }
+                .into()
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_matches_thiserror_derive() {
+        for (manual, derived) in pairs() {
+            assert_eq!(chain(&manual), chain(&derived), "{:?}", manual);
+        }
+    }
+
+    #[test]
+    fn test_source_chain() {
+        let err = Error::Io {
+            context: "loading data".to_string(),
+            source: io_error(),
+        };
+        let source = err.source().unwrap();
+        assert!(source.downcast_ref::<io::Error>().is_some());
+
+        let err = Error::from(diagnostic());
+        assert_eq!(err.to_string(), "service.conf:1:8: invalid port");
+        assert!(err.source().is_none());
+
+        assert!(validate_input("").unwrap_err().source().is_none());
+    }
+
+    #[test]
+    fn test_question_mark_converts() {
+        fn parse(s: &str) -> Result<i32> {
+            Ok(s.parse::<i32>()?)
+        }
+        assert!(matches!(parse("x"), Err(Error::Parse(_))));
+
+        let err = load_config("/nonexistent/config").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "IO error: reading config from /nonexistent/config"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Exit code tests for anyhow.rs
+//!
+//! Runs the built binary once per failure mode and checks its sysexits.h
+//! code, and that the error is reported exactly once. Place in `tests/` of
+//! the crate whose binary is anyhow.rs, named `app`; the config fixtures are
+//! read from `fixtures/` next to `tests/`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+// The codes of `ExitKind` in anyhow.rs
+const EX_USAGE: i32 = 64;
+const EX_UNAVAILABLE: i32 = 69;
+const EX_SOFTWARE: i32 = 70;
+const EX_IOERR: i32 = 74;
+const EX_CONFIG: i32 = 78;
+
+const VALID: &str = include_str!("../fixtures/valid.conf");
+
+/// Runs the binary with `args`, returning its exit code and stderr.
+fn run(args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_app"))
+        .args(args)
+        .env_remove("LOG_FORMAT")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    (output.status.code().unwrap(), stderr)
+}
+
+/// Runs the binary with `contents` as its config file.
+fn run_with_config(test: &str, contents: &str) -> (i32, String) {
+    let dir = TempDir::new(test);
+    let path = dir.0.join("config.toml");
+    fs::write(&path, contents).unwrap();
+    run(&["--config", path.to_str().unwrap()])
+}
+
+fn assert_fails_with(result: (i32, String), expected: i32) {
+    let (code, stderr) = result;
+    assert_eq!(code, expected, "{}", stderr);
+    assert_eq!(stderr.matches("Error:").count(), 1, "{}", stderr);
+}
+
+/// A directory of its own for one test, removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(test: &str) -> Self {
+        let name = format!("anyhow-exit-codes-{}-{}", std::process::id(), test);
+        let path = std::env::temp_dir().join(name);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn test_valid_config_succeeds() {
+    let (code, stderr) = run_with_config("valid", VALID);
+    assert_eq!(code, 0, "{}", stderr);
+    assert!(stderr.is_empty(), "{}", stderr);
+}
+
+#[test]
+fn test_bad_argument_is_usage_error() {
+    assert_fails_with(run(&["--bogus"]), EX_USAGE);
+    assert_fails_with(run(&["--config"]), EX_USAGE);
+}
+
+#[test]
+fn test_syntax_error_is_config_error() {
+    let source = include_str!("../fixtures/bad-port.conf");
+    assert_fails_with(run_with_config("syntax", source), EX_CONFIG);
+}
+
+#[test]
+fn test_missing_key_is_config_error() {
+    let source = include_str!("../fixtures/missing-key.conf");
+    assert_fails_with(run_with_config("missing-key", source), EX_CONFIG);
+}
+
+#[test]
+fn test_failed_validation_is_config_error() {
+    let source = VALID.replace("= 30", "= 0");
+    assert_fails_with(run_with_config("validation", &source), EX_CONFIG);
+}
+
+#[test]
+fn test_unreadable_config_is_io_error() {
+    // A directory cannot be read as a file
+    let dir = TempDir::new("unreadable");
+    assert_fails_with(run(&["--config", dir.0.to_str().unwrap()]), EX_IOERR);
+}
+
+#[test]
+fn test_unreachable_database_is_unavailable() {
+    let source = VALID.replace("localhost", "unavailable");
+    assert_fails_with(run_with_config("unavailable", &source), EX_UNAVAILABLE);
+}
+
+#[test]
+fn test_unexpected_failure_is_internal() {
+    let source = VALID.replace("localhost", "broken");
+    assert_fails_with(run_with_config("internal", &source), EX_SOFTWARE);
+}
@@ -1,13 +1,41 @@
 //! Error type template for libraries using thiserror
 //!
+//! Services enable the `axum` feature to get `IntoResponse` for `Error`,
+//! built on the HTTP/gRPC mapping in `StatusError`. The `trace` feature
+//! makes `Traced` capture a backtrace and tracing span trace.
+//!
 //! Add to Cargo.toml:
 //! ```toml
 //! [dependencies]
 //! thiserror = "1"
+//! serde = { version = "1", features = ["derive"] }
+//! serde_json = "1"
+//! axum = { version = "0.8", optional = true }
+//! tracing = { version = "0.1", optional = true }
+//! tracing-error = { version = "0.2", optional = true }
+//!
+//! [dev-dependencies]
+//! tokio = { version = "1", features = ["macros", "rt"] }
+//! tower = { version = "0.5", features = ["util"] }
+//! tracing-subscriber = "0.3"
+//!
+//! [features]
+//! axum = ["dep:axum", "dep:tracing"]
+//! trace = ["dep:tracing", "dep:tracing-error"]
 //! ```
 
-use thiserror::Error;
+use std::backtrace::Backtrace;
+#[cfg(feature = "trace")]
+use std::backtrace::BacktraceStatus;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::io;
+use std::ops::Range;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+#[cfg(feature = "trace")]
+use tracing_error::{SpanTrace, SpanTraceStatus};
 
 /// Main error type for the library
 #[derive(Error, Debug)]
@@ -39,18 +67,544 @@ pub enum Error {
     /// Wrapped external error
     #[error(transparent)]
     External(#[from] ExternalError),
+
+    /// Invalid input file, with the location of the problem
+    #[error(transparent)]
+    Diagnostic(Box<Diagnostic>),
 }
 
 /// Example external error that can be wrapped
+///
+/// Built with `new` so that fields can be added without breaking callers.
 #[derive(Error, Debug)]
 #[error("external service error: {message}")]
+#[non_exhaustive]
 pub struct ExternalError {
     pub message: String,
+    /// Set when the service asked us to back off (e.g. HTTP 429 Retry-After)
+    pub retry_after: Option<Duration>,
+}
+
+impl ExternalError {
+    pub fn new(message: impl Into<String>) -> Self {
+        ExternalError {
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    /// The service asked us to wait `delay` before trying again.
+    pub fn with_retry_after(mut self, delay: Duration) -> Self {
+        self.retry_after = Some(delay);
+        self
+    }
 }
 
 /// Result alias for convenience
 pub type Result<T> = std::result::Result<T, Error>;
 
+// =====================================================
+// Error Codes
+// =====================================================
+
+/// Stable, machine-readable codes, one per variant.
+///
+/// Clients branch on these instead of parsing messages, so a code is never
+/// changed or reused once released. New variants get new codes and are
+/// appended to `REGISTRY`.
+pub mod codes {
+    pub const CONFIG: &str = "CFG001";
+    pub const NOT_FOUND: &str = "NF404";
+    pub const INVALID_INPUT: &str = "INP400";
+    pub const IO: &str = "IO500";
+    pub const PARSE: &str = "PRS001";
+    pub const EXTERNAL: &str = "EXT502";
+    pub const DIAGNOSTIC: &str = "CFG002";
+
+    /// Every code ever issued, in release order.
+    pub const REGISTRY: &[&str] = &[
+        CONFIG,
+        NOT_FOUND,
+        INVALID_INPUT,
+        IO,
+        PARSE,
+        EXTERNAL,
+        DIAGNOSTIC,
+    ];
+}
+
+impl Error {
+    /// The stable code for this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Config { .. } => codes::CONFIG,
+            Error::NotFound { .. } => codes::NOT_FOUND,
+            Error::InvalidInput(_) => codes::INVALID_INPUT,
+            Error::Io { .. } => codes::IO,
+            Error::Parse(_) => codes::PARSE,
+            Error::External(_) => codes::EXTERNAL,
+            Error::Diagnostic(_) => codes::DIAGNOSTIC,
+        }
+    }
+
+    /// Structured fields of the variant, for reports and logs.
+    pub fn context(&self) -> BTreeMap<String, String> {
+        let fields: Vec<(&str, String)> = match self {
+            Error::Config { message } => vec![("message", message.clone())],
+            Error::NotFound { resource_type, id } => {
+                vec![("resource_type", resource_type.clone()), ("id", id.clone())]
+            }
+            Error::InvalidInput(reason) => vec![("reason", reason.clone())],
+            Error::Io { context, .. } => vec![("context", context.clone())],
+            Error::Parse(_) => vec![],
+            Error::External(err) => vec![("message", err.message.clone())],
+            Error::Diagnostic(diag) => vec![
+                ("file", diag.file.clone()),
+                ("line", diag.span.line.to_string()),
+                ("column", diag.span.column.to_string()),
+                ("label", diag.label.clone()),
+            ],
+        };
+        fields
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect()
+    }
+
+    /// Full snapshot of this error, for logs.
+    ///
+    /// The sources and context can hold paths, config values and upstream
+    /// replies; responses use `public_report` instead.
+    pub fn report(&self) -> ErrorReport {
+        let mut sources = Vec::new();
+        let mut next = std::error::Error::source(self);
+        while let Some(cause) = next {
+            sources.push(cause.to_string());
+            next = cause.source();
+        }
+
+        ErrorReport {
+            code: self.code().to_string(),
+            message: self.to_string(),
+            sources,
+            context: self.context(),
+        }
+    }
+}
+
+// =====================================================
+// Error Report
+// =====================================================
+
+/// Serializable form of an `Error`.
+///
+/// `Error::report` fills in everything, for logs; `Error::public_report`
+/// only what clients may see. Deserializable too, so clients can share the
+/// type and match on `code`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorReport {
+    pub code: String,
+    pub message: String,
+    /// Display of each cause, outermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub context: BTreeMap<String, String>,
+}
+
+impl From<&Error> for ErrorReport {
+    fn from(err: &Error) -> Self {
+        err.report()
+    }
+}
+
+// =====================================================
+// Diagnostics
+// =====================================================
+
+/// Location in a file: 1-based line and column, length in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+/// An error pinned to a span of an input file.
+///
+/// Display is the one-line `file:line:col: message` form. `render` gives the
+/// compiler-style report with the offending line; the serialized form
+/// carries the same data for editors and log pipelines.
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[error("{file}:{}:{}: {message}", .span.line, .span.column)]
+pub struct Diagnostic {
+    pub message: String,
+    pub file: String,
+    pub span: Span,
+    /// Printed under the span: what was expected there
+    pub label: String,
+    /// The line the span is on, without its line ending
+    pub snippet: String,
+}
+
+impl Diagnostic {
+    /// Diagnostic for the byte range `at` of `source`, read from `file`.
+    ///
+    /// `at` must lie on char boundaries. A range running past the end of
+    /// its line is cut there.
+    pub fn new(
+        file: &str,
+        source: &str,
+        at: Range<usize>,
+        message: impl Into<String>,
+        label: impl Into<String>,
+    ) -> Self {
+        let line_start = source[..at.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[at.start..]
+            .find('\n')
+            .map_or(source.len(), |i| at.start + i);
+        let snippet = source[line_start..line_end].trim_end_matches('\r');
+        // A range starting on the line ending points just past the snippet
+        let snippet_end = line_start + snippet.len();
+        let start = at.start.min(snippet_end);
+        let end = at.end.clamp(start, snippet_end);
+
+        Diagnostic {
+            message: message.into(),
+            file: file.to_string(),
+            span: Span {
+                line: source[..at.start].matches('\n').count() + 1,
+                column: source[line_start..start].chars().count() + 1,
+                len: source[start..end].chars().count(),
+            },
+            label: label.into(),
+            snippet: snippet.to_string(),
+        }
+    }
+
+    /// Compiler-style report:
+    ///
+    /// ```text
+    /// error: invalid port
+    ///  --> service.conf:1:8
+    ///   |
+    /// 1 | port = 80x80
+    ///   |        ^^^^^ expected an integer from 0 to 65535
+    /// ```
+    pub fn render(&self) -> String {
+        let line = self.span.line.to_string();
+        let gutter = " ".repeat(line.len());
+        // Tabs stay tabs so the carets line up under the snippet
+        let indent: String = self
+            .snippet
+            .chars()
+            .take(self.span.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = "^".repeat(self.span.len.max(1));
+
+        format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{} {}\n",
+            self.message,
+            gutter,
+            self.file,
+            line,
+            self.span.column,
+            gutter,
+            line,
+            self.snippet,
+            gutter,
+            indent,
+            carets,
+            self.label
+        )
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Diagnostic has only string and integer fields")
+    }
+}
+
+// Boxed in Error: four strings would otherwise double the size of every Result
+impl From<Diagnostic> for Error {
+    fn from(diag: Diagnostic) -> Self {
+        Error::Diagnostic(Box::new(diag))
+    }
+}
+
+// =====================================================
+// Status Mapping
+// =====================================================
+
+/// gRPC status codes; the values match `tonic::Code`, so
+/// `tonic::Code::from(code as i32)` converts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum GrpcCode {
+    Ok = 0,
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+/// How an error is presented at a service boundary.
+///
+/// The public message goes to clients; the internal one, with the full
+/// source chain, goes to logs only.
+pub trait StatusError: std::error::Error {
+    fn http_status(&self) -> u16;
+
+    fn grpc_code(&self) -> GrpcCode;
+
+    /// Message safe to return to clients
+    fn public_message(&self) -> String;
+
+    /// Display of the error and every source, for logs
+    fn internal_message(&self) -> String {
+        let mut msg = self.to_string();
+        let mut next = self.source();
+        while let Some(cause) = next {
+            msg.push_str(": ");
+            msg.push_str(&cause.to_string());
+            next = cause.source();
+        }
+        msg
+    }
+}
+
+impl StatusError for Error {
+    fn http_status(&self) -> u16 {
+        match self {
+            Error::InvalidInput(_) | Error::Parse(_) => 400,
+            Error::NotFound { .. } => 404,
+            Error::Config { .. } | Error::Io { .. } | Error::Diagnostic(_) => 500,
+            Error::External(_) => 502,
+        }
+    }
+
+    fn grpc_code(&self) -> GrpcCode {
+        match self {
+            Error::InvalidInput(_) | Error::Parse(_) => GrpcCode::InvalidArgument,
+            Error::NotFound { .. } => GrpcCode::NotFound,
+            Error::Config { .. } | Error::Io { .. } | Error::Diagnostic(_) => GrpcCode::Internal,
+            Error::External(_) => GrpcCode::Unavailable,
+        }
+    }
+
+    fn public_message(&self) -> String {
+        match self {
+            // Caused by the request: the client needs the details to fix it
+            Error::InvalidInput(_) | Error::Parse(_) | Error::NotFound { .. } => self.to_string(),
+            // Server-side: paths, config values and upstream replies stay in logs
+            Error::Config { .. } | Error::Io { .. } | Error::Diagnostic(_) => {
+                "internal server error".to_string()
+            }
+            Error::External(_) => "upstream service unavailable".to_string(),
+        }
+    }
+}
+
+impl Error {
+    /// Report safe to return to clients: the public message, and the
+    /// context only when the client caused the error. Sources never leave
+    /// the server.
+    pub fn public_report(&self) -> ErrorReport {
+        let client_error = self.http_status() < 500;
+        ErrorReport {
+            code: self.code().to_string(),
+            message: self.public_message(),
+            sources: Vec::new(),
+            context: if client_error {
+                self.context()
+            } else {
+                BTreeMap::new()
+            },
+        }
+    }
+}
+
+// =====================================================
+// Retry Classification
+// =====================================================
+
+/// Whether the operation that failed is worth retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryClass {
+    /// Transient: retry with backoff
+    Retryable,
+    /// Retrying gives the same result
+    Permanent,
+    /// Transient, but not before the given delay
+    RateLimited { retry_after: Duration },
+}
+
+/// Classifies an error for retry decisions.
+pub trait ClassifyRetry {
+    fn retry_class(&self) -> RetryClass;
+
+    fn is_retryable(&self) -> bool {
+        self.retry_class() != RetryClass::Permanent
+    }
+}
+
+impl ClassifyRetry for io::ErrorKind {
+    fn retry_class(&self) -> RetryClass {
+        use io::ErrorKind::*;
+        match self {
+            Interrupted | WouldBlock | TimedOut | ConnectionRefused | ConnectionReset
+            | ConnectionAborted | NotConnected | BrokenPipe => RetryClass::Retryable,
+            _ => RetryClass::Permanent,
+        }
+    }
+}
+
+impl ClassifyRetry for io::Error {
+    fn retry_class(&self) -> RetryClass {
+        self.kind().retry_class()
+    }
+}
+
+impl ClassifyRetry for ExternalError {
+    fn retry_class(&self) -> RetryClass {
+        match self.retry_after {
+            Some(retry_after) => RetryClass::RateLimited { retry_after },
+            // Upstream failures are assumed transient
+            None => RetryClass::Retryable,
+        }
+    }
+}
+
+impl ClassifyRetry for Error {
+    fn retry_class(&self) -> RetryClass {
+        match self {
+            // Bad config, input or data fail the same way every time
+            Error::Config { .. }
+            | Error::NotFound { .. }
+            | Error::InvalidInput(_)
+            | Error::Parse(_)
+            | Error::Diagnostic(_) => RetryClass::Permanent,
+            Error::Io { source, .. } => source.retry_class(),
+            Error::External(err) => err.retry_class(),
+        }
+    }
+}
+
+// =====================================================
+// Backtrace and Span Trace
+// =====================================================
+
+/// An error plus where it was created.
+///
+/// With the `trace` feature, `new` captures a `Backtrace` (when
+/// RUST_BACKTRACE or RUST_LIB_BACKTRACE enables it) and the tracing
+/// `SpanTrace` (when the subscriber has an `ErrorLayer`). Without the
+/// feature it captures nothing and is the size of `E`; with it, one pointer
+/// larger.
+///
+/// Display and `source()` forward to the inner error, so `{:#}` chains read
+/// the same as for a bare `Error`; `{:?}` appends the traces.
+pub struct Traced<E = Error> {
+    error: E,
+    // Boxed: a Backtrace alone would make every Result three times larger
+    #[cfg(feature = "trace")]
+    captured: Box<Captured>,
+}
+
+#[cfg(feature = "trace")]
+struct Captured {
+    backtrace: Backtrace,
+    span_trace: SpanTrace,
+}
+
+impl<E> Traced<E> {
+    pub fn new(error: E) -> Self {
+        Traced {
+            error,
+            #[cfg(feature = "trace")]
+            captured: Box::new(Captured {
+                backtrace: Backtrace::capture(),
+                span_trace: SpanTrace::capture(),
+            }),
+        }
+    }
+
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    pub fn into_inner(self) -> E {
+        self.error
+    }
+
+    /// The backtrace, if one was captured.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        #[cfg(feature = "trace")]
+        if self.captured.backtrace.status() == BacktraceStatus::Captured {
+            return Some(&self.captured.backtrace);
+        }
+        None
+    }
+
+    /// Spans that were active at construction, if any were recorded.
+    #[cfg(feature = "trace")]
+    pub fn span_trace(&self) -> Option<&SpanTrace> {
+        let span_trace = &self.captured.span_trace;
+        (span_trace.status() == SpanTraceStatus::CAPTURED).then_some(span_trace)
+    }
+}
+
+impl<E: ClassifyRetry> ClassifyRetry for Traced<E> {
+    fn retry_class(&self) -> RetryClass {
+        self.error.retry_class()
+    }
+}
+
+// ? converts anything that converts to Error, capturing at that point
+impl<T: Into<Error>> From<T> for Traced<Error> {
+    fn from(err: T) -> Self {
+        Traced::new(err.into())
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for Traced<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Debug for Traced<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.error, f)?;
+        if let Some(backtrace) = self.backtrace() {
+            write!(f, "\n\nBacktrace:\n{}", backtrace)?;
+        }
+        #[cfg(feature = "trace")]
+        if let Some(span_trace) = self.span_trace() {
+            write!(f, "\n\nSpan trace:\n{}", span_trace)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for Traced<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
 // =====================================================
 // Usage Examples
 // =====================================================
@@ -62,10 +616,102 @@ pub fn load_config(path: &str) -> Result<Config> {
             source: e,
         })?;
 
-    // Parse would use ? with #[from]
-    let _value: i32 = content.trim().parse()?;
+    parse_config(path, &content)
+}
+
+/// Parses `key = value` lines. Blank lines and lines starting with `#` are
+/// skipped; string values may be double-quoted.
+pub fn parse_config(file: &str, source: &str) -> Result<Config> {
+    let mut port = None;
+    let mut db_url = None;
+    let mut timeout_secs = None;
+
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        let text = line.trim();
+        let start = offset + (line.len() - line.trim_start().len());
+        offset += line.len();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        // Diagnostic converts into Error with ?
+        let at = |range: Range<usize>| start + range.start..start + range.end;
+        let Some(eq) = text.find('=') else {
+            let at = at(0..text.len());
+            Err(Diagnostic::new(
+                file,
+                source,
+                at,
+                "expected `key = value`",
+                "missing `=`",
+            ))?
+        };
+        let key = text[..eq].trim_end();
+        let value = text[eq + 1..].trim_start();
+        let value_at = at(text.len() - value.len()..text.len());
+
+        match key {
+            "port" => {
+                let parsed = value.parse().map_err(|_| {
+                    let label = "expected an integer from 0 to 65535";
+                    Diagnostic::new(file, source, value_at, "invalid port", label)
+                })?;
+                port = Some(parsed);
+            }
+            "db_url" => db_url = Some(unquote(file, source, value, value_at)?),
+            "timeout_secs" => {
+                let parsed = value.parse().map_err(|_| {
+                    let label = "expected a whole number of seconds";
+                    Diagnostic::new(file, source, value_at, "invalid timeout", label)
+                })?;
+                timeout_secs = Some(parsed);
+            }
+            _ => Err(Diagnostic::new(
+                file,
+                source,
+                at(0..key.len()),
+                format!("unknown key `{}`", key),
+                "expected `port`, `db_url` or `timeout_secs`",
+            ))?,
+        }
+    }
+
+    // A missing key has no location to point at
+    let missing = |key: &str| Error::Config {
+        message: format!("{}: missing key `{}`", file, key),
+    };
+    Ok(Config {
+        port: port.ok_or_else(|| missing("port"))?,
+        db_url: db_url.ok_or_else(|| missing("db_url"))?,
+        timeout_secs: timeout_secs.ok_or_else(|| missing("timeout_secs"))?,
+    })
+}
+
+fn unquote(
+    file: &str,
+    source: &str,
+    value: &str,
+    at: Range<usize>,
+) -> std::result::Result<String, Diagnostic> {
+    let Some(quoted) = value.strip_prefix('"') else {
+        return Ok(value.to_string());
+    };
+    quoted.strip_suffix('"').map(str::to_string).ok_or_else(|| {
+        Diagnostic::new(
+            file,
+            source,
+            at,
+            "unterminated string",
+            "missing closing `\"`",
+        )
+    })
+}
 
-    Ok(Config { /* ... */ })
+/// `load_config` for callers that want to know where a failure happened.
+#[cfg_attr(feature = "trace", tracing::instrument)]
+pub fn load_config_traced(path: &str) -> std::result::Result<Config, Traced> {
+    Ok(load_config(path)?)
 }
 
 pub fn get_user(id: &str) -> Result<User> {
@@ -87,9 +733,62 @@ pub fn validate_input(input: &str) -> Result<()> {
 }
 
 // Placeholder types for examples
-pub struct Config;
+#[derive(Debug)]
+pub struct Config {
+    pub port: u16,
+    pub db_url: String,
+    pub timeout_secs: u64,
+}
 pub struct User;
 
+// =====================================================
+// HTTP Handler (feature = "axum")
+// =====================================================
+
+#[cfg(feature = "axum")]
+pub mod http {
+    use super::{get_user, load_config, validate_input, Error, StatusError};
+    use axum::extract::Path;
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+
+    pub const CONFIG_PATH: &str = "/etc/example-service/config.toml";
+
+    /// The body of every error response is the error's `public_report`.
+    impl IntoResponse for Error {
+        fn into_response(self) -> Response {
+            let status = StatusCode::from_u16(self.http_status())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            if status.is_server_error() {
+                // Only the log sees the internal message
+                tracing::error!(code = self.code(), "{}", self.internal_message());
+            }
+            (status, Json(self.public_report())).into_response()
+        }
+    }
+
+    // Handlers return Result<_, Error> and use ? as usual
+
+    async fn show_user(Path(id): Path<String>) -> Result<String, Error> {
+        validate_input(&id)?;
+        get_user(&id)?;
+        Ok(format!("user {}", id))
+    }
+
+    async fn reload_config() -> Result<StatusCode, Error> {
+        load_config(CONFIG_PATH)?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    pub fn router() -> Router {
+        Router::new()
+            .route("/users/{id}", get(show_user))
+            .route("/config/reload", post(reload_config))
+    }
+}
+
 // =====================================================
 // Tests
 // =====================================================
@@ -97,6 +796,8 @@ pub struct User;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
+    use std::error::Error as _;
 
     #[test]
     fn test_not_found_error() {
@@ -118,4 +819,412 @@ mod tests {
         // Error chain is preserved
         assert!(err.source().is_some());
     }
+
+    /// One error per variant; the match keeps it exhaustive.
+    fn one_of_each() -> Vec<Error> {
+        let all = vec![
+            Error::Config {
+                message: "missing port".to_string(),
+            },
+            Error::NotFound {
+                resource_type: "user".to_string(),
+                id: "123".to_string(),
+            },
+            Error::InvalidInput("input cannot be empty".to_string()),
+            Error::Io {
+                context: "loading data".to_string(),
+                source: io::Error::new(io::ErrorKind::NotFound, "file missing"),
+            },
+            Error::Parse("x".parse::<i32>().unwrap_err()),
+            Error::External(ExternalError::new("timeout")),
+            Diagnostic::new(
+                "service.conf",
+                "port = x",
+                7..8,
+                "invalid port",
+                "expected an integer",
+            )
+            .into(),
+        ];
+        for err in &all {
+            match err {
+                Error::Config { .. }
+                | Error::NotFound { .. }
+                | Error::InvalidInput(_)
+                | Error::Io { .. }
+                | Error::Parse(_)
+                | Error::External(_)
+                | Error::Diagnostic(_) => {}
+            }
+        }
+        all
+    }
+
+    #[test]
+    fn test_codes_are_unique() {
+        let unique: HashSet<_> = codes::REGISTRY.iter().collect();
+        assert_eq!(unique.len(), codes::REGISTRY.len());
+
+        let used: HashSet<_> = one_of_each().iter().map(Error::code).collect();
+        assert_eq!(
+            used.len(),
+            codes::REGISTRY.len(),
+            "two variants share a code"
+        );
+    }
+
+    #[test]
+    fn test_codes_are_stable() {
+        // Released codes. Append here when adding a variant; editing or
+        // removing an entry breaks every client that matches on it.
+        assert_eq!(
+            codes::REGISTRY,
+            ["CFG001", "NF404", "INP400", "IO500", "PRS001", "EXT502", "CFG002"]
+        );
+        for err in one_of_each() {
+            assert!(
+                codes::REGISTRY.contains(&err.code()),
+                "{} not registered",
+                err.code()
+            );
+        }
+    }
+
+    #[test]
+    fn test_report_json() {
+        let err = Error::Io {
+            context: "loading data".to_string(),
+            source: io::Error::new(io::ErrorKind::NotFound, "file missing"),
+        };
+        let json = serde_json::to_value(err.report()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "code": "IO500",
+                "message": "IO error: loading data",
+                "sources": ["file missing"],
+                "context": { "context": "loading data" },
+            })
+        );
+
+        let back: ErrorReport = serde_json::from_value(json).unwrap();
+        assert_eq!(back, err.report());
+    }
+
+    #[test]
+    fn test_report_context_fields() {
+        let err = get_user("unknown").err().unwrap();
+        let report = ErrorReport::from(&err);
+        assert_eq!(report.code, codes::NOT_FOUND);
+        assert_eq!(report.context["resource_type"], "user");
+        assert_eq!(report.context["id"], "unknown");
+        assert!(report.sources.is_empty());
+    }
+
+    #[test]
+    fn test_status_mapping() {
+        let expected = [
+            (codes::CONFIG, 500, GrpcCode::Internal),
+            (codes::NOT_FOUND, 404, GrpcCode::NotFound),
+            (codes::INVALID_INPUT, 400, GrpcCode::InvalidArgument),
+            (codes::IO, 500, GrpcCode::Internal),
+            (codes::PARSE, 400, GrpcCode::InvalidArgument),
+            (codes::EXTERNAL, 502, GrpcCode::Unavailable),
+            (codes::DIAGNOSTIC, 500, GrpcCode::Internal),
+        ];
+        for (err, (code, http, grpc)) in one_of_each().iter().zip(expected) {
+            assert_eq!(err.code(), code);
+            assert_eq!(err.http_status(), http, "{}", code);
+            assert_eq!(err.grpc_code(), grpc, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_public_message_hides_internals() {
+        let err = load_config("/nonexistent/secret.toml").err().unwrap();
+        assert_eq!(err.public_message(), "internal server error");
+
+        let internal = err.internal_message();
+        assert!(internal.starts_with("IO error: reading config from /nonexistent/secret.toml: "));
+
+        let report = err.public_report();
+        assert_eq!(report.message, "internal server error");
+        assert!(report.sources.is_empty());
+        assert!(report.context.is_empty(), "{:?}", report.context);
+
+        let err = validate_input("").unwrap_err();
+        assert_eq!(err.public_message(), "invalid input: input cannot be empty");
+        assert_eq!(
+            err.public_report().context["reason"],
+            "input cannot be empty"
+        );
+    }
+
+    #[test]
+    fn test_retry_classification() {
+        let expected = [
+            RetryClass::Permanent,
+            RetryClass::Permanent,
+            RetryClass::Permanent,
+            // one_of_each's Io source is NotFound
+            RetryClass::Permanent,
+            RetryClass::Permanent,
+            RetryClass::Retryable,
+            RetryClass::Permanent,
+        ];
+        for (err, class) in one_of_each().iter().zip(expected) {
+            assert_eq!(err.retry_class(), class, "{}", err.code());
+        }
+
+        let timeout = Error::Io {
+            context: "connecting".to_string(),
+            source: io::Error::new(io::ErrorKind::TimedOut, "timed out"),
+        };
+        assert_eq!(timeout.retry_class(), RetryClass::Retryable);
+        assert!(Traced::new(timeout).is_retryable());
+
+        let throttled = Error::External(
+            ExternalError::new("too many requests").with_retry_after(Duration::from_secs(30)),
+        );
+        assert_eq!(
+            throttled.retry_class(),
+            RetryClass::RateLimited {
+                retry_after: Duration::from_secs(30)
+            }
+        );
+        assert!(throttled.is_retryable());
+    }
+
+    #[test]
+    fn test_io_kind_classification() {
+        for kind in [
+            io::ErrorKind::Interrupted,
+            io::ErrorKind::TimedOut,
+            io::ErrorKind::ConnectionReset,
+        ] {
+            assert!(kind.is_retryable(), "{:?}", kind);
+        }
+        for kind in [
+            io::ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied,
+            io::ErrorKind::InvalidData,
+        ] {
+            assert!(!kind.is_retryable(), "{:?}", kind);
+        }
+    }
+
+    const VALID: &str = include_str!("fixtures/valid.conf");
+    const MALFORMED: [(&str, &str); 4] = [
+        ("bad-port.conf", include_str!("fixtures/bad-port.conf")),
+        (
+            "missing-equals.conf",
+            include_str!("fixtures/missing-equals.conf"),
+        ),
+        (
+            "unknown-key.conf",
+            include_str!("fixtures/unknown-key.conf"),
+        ),
+        (
+            "unterminated-string.conf",
+            include_str!("fixtures/unterminated-string.conf"),
+        ),
+    ];
+
+    #[test]
+    fn test_parse_valid_fixture() {
+        let config = parse_config("valid.conf", VALID).unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.db_url, "postgres://localhost/db");
+        assert_eq!(config.timeout_secs, 30);
+    }
+
+    #[test]
+    fn test_malformed_fixtures_point_at_problem() {
+        let expected = [
+            "bad-port.conf:1:8: invalid port",
+            "missing-equals.conf:2:1: expected `key = value`",
+            "unknown-key.conf:3:1: unknown key `timeout`",
+            "unterminated-string.conf:2:10: unterminated string",
+        ];
+        for ((file, source), message) in MALFORMED.iter().zip(expected) {
+            let err = parse_config(file, source).unwrap_err();
+            assert_eq!(err.code(), codes::DIAGNOSTIC);
+            assert_eq!(err.to_string(), message);
+        }
+
+        let err = parse_config(
+            "missing-key.conf",
+            include_str!("fixtures/missing-key.conf"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "configuration error: missing-key.conf: missing key `timeout_secs`"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_render() {
+        let Error::Diagnostic(diag) = parse_config(MALFORMED[0].0, MALFORMED[0].1).unwrap_err()
+        else {
+            panic!("expected a diagnostic");
+        };
+        assert_eq!(
+            diag.render(),
+            "\
+error: invalid port
+ --> bad-port.conf:1:8
+  |
+1 | port = 80x80
+  |        ^^^^^ expected an integer from 0 to 65535
+"
+        );
+
+        let Error::Diagnostic(diag) = parse_config(MALFORMED[1].0, MALFORMED[1].1).unwrap_err()
+        else {
+            panic!("expected a diagnostic");
+        };
+        assert!(diag
+            .render()
+            .ends_with("2 | db_url \"postgres://localhost/db\"\n  | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ missing `=`\n"));
+    }
+
+    #[test]
+    fn test_diagnostic_json() {
+        let Error::Diagnostic(diag) = parse_config(MALFORMED[2].0, MALFORMED[2].1).unwrap_err()
+        else {
+            panic!("expected a diagnostic");
+        };
+        let json: serde_json::Value = serde_json::from_str(&diag.to_json()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "message": "unknown key `timeout`",
+                "file": "unknown-key.conf",
+                "span": { "line": 3, "column": 1, "len": 7 },
+                "label": "expected `port`, `db_url` or `timeout_secs`",
+                "snippet": "timeout = 30",
+            })
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_span_is_in_chars() {
+        let diag = Diagnostic::new("x.conf", "a\n\tname = \"é\n", 10..14, "bad", "here");
+        assert_eq!(
+            diag.span,
+            Span {
+                line: 2,
+                column: 9,
+                len: 2
+            }
+        );
+        assert!(diag.render().ends_with("  | \t       ^^ here\n"));
+    }
+
+    #[test]
+    fn test_diagnostic_on_crlf_line_ending() {
+        let past_end = Span {
+            line: 1,
+            column: 2,
+            len: 0,
+        };
+        // Both the \r and the \n are outside the snippet
+        for at in [1..2, 2..3, 1..3] {
+            let diag = Diagnostic::new("f", "a\r\n", at.clone(), "bad", "here");
+            assert_eq!(diag.span, past_end, "{:?}", at);
+            assert_eq!(diag.snippet, "a");
+        }
+    }
+
+    #[test]
+    fn test_traced_is_transparent() {
+        let err = load_config_traced("/nonexistent/config").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "IO error: reading config from /nonexistent/config"
+        );
+        assert!(err.source().unwrap().downcast_ref::<io::Error>().is_some());
+        assert_eq!(err.error().code(), codes::IO);
+
+        let err: Traced = "x".parse::<i32>().unwrap_err().into();
+        assert!(matches!(err.into_inner(), Error::Parse(_)));
+    }
+
+    #[cfg(not(feature = "trace"))]
+    #[test]
+    fn test_traced_captures_nothing_by_default() {
+        let err = Traced::new(Error::InvalidInput("x".to_string()));
+        assert!(err.backtrace().is_none());
+        assert_eq!(size_of::<Traced>(), size_of::<Error>());
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn test_traced_captures_span_trace() {
+        use tracing_error::ErrorLayer;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+        let err = tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request", id = 7)
+                .in_scope(|| load_config_traced("/nonexistent/config"))
+                .err()
+                .unwrap()
+        });
+
+        let spans = err.span_trace().unwrap().to_string();
+        assert!(spans.contains("load_config_traced"), "{}", spans);
+        assert!(spans.contains("request"), "{}", spans);
+        assert!(format!("{:?}", err).contains("Span trace:"));
+    }
+
+    #[cfg(feature = "axum")]
+    mod http_handler {
+        use super::super::http::router;
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use tower::ServiceExt;
+
+        async fn send(method: &str, uri: &str) -> (StatusCode, String) {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = router().oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+
+        #[tokio::test]
+        async fn test_found() {
+            assert_eq!(
+                send("GET", "/users/42").await,
+                (StatusCode::OK, "user 42".to_string())
+            );
+        }
+
+        #[tokio::test]
+        async fn test_not_found_is_404_with_code() {
+            let (status, body) = send("GET", "/users/unknown").await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            assert_eq!(
+                body,
+                r#"{"code":"NF404","message":"resource not found: user/unknown","context":{"id":"unknown","resource_type":"user"}}"#
+            );
+        }
+
+        #[tokio::test]
+        async fn test_io_error_is_500_without_context() {
+            let (status, body) = send("POST", "/config/reload").await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+            assert_eq!(
+                body,
+                r#"{"code":"IO500","message":"internal server error"}"#
+            );
+            assert!(!body.contains("config.toml"));
+        }
+    }
 }
@@ -0,0 +1,76 @@
+//! Build script for the FFI templates
+//!
+//! Compiles the C sources in `c/` for the FFI templates. Place next to
+//! Cargo.toml; in a real project each template is its own crate, so keep
+//! only the libraries it needs.
+//!
+//! Static libraries are linked in; the plugins for dynamic-loading.rs are
+//! shared objects written to OUT_DIR, with their paths passed to the crate
+//! as environment variables. That part assumes a gcc/clang-style compiler.
+//!
+//! Add to Cargo.toml:
+//! ```toml
+//! [build-dependencies]
+//! cc = "1"
+//! ```
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=c");
+
+    // Mock vendor library wrapped by safe-wrapper.rs
+    cc::Build::new()
+        .file("c/mocklib.c")
+        .include("c")
+        .flag_if_supported("-std=c11")
+        .warnings(true)
+        .compile("mocklib");
+
+    // System-header layout facts checked by c-bindings.rs tests
+    cc::Build::new()
+        .file("c/c_bindings_layout.c")
+        .flag_if_supported("-std=c11")
+        .warnings(true)
+        .compile("c_bindings_layout");
+
+    // C consumer of expose-api.rs, called from its tests
+    cc::Build::new()
+        .file("c/expose_api_test.c")
+        .include("c")
+        .flag_if_supported("-std=c11")
+        .warnings(true)
+        .compile("expose_api_test");
+
+    // Shared objects opened at runtime by dynamic-loading.rs
+    let plugins: [(&str, &str, &[&str]); 3] = [
+        ("PLUGIN_PATH", "plugin", &[]),
+        ("PLUGIN_OLD_ABI_PATH", "plugin_old_abi", &["-DPLUGIN_ABI_VERSION=1"]),
+        ("PLUGIN_MINIMAL_PATH", "plugin_minimal", &["-DPLUGIN_MINIMAL"]),
+    ];
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    let compiler = cc::Build::new()
+        .flag_if_supported("-std=c11")
+        .warnings(true)
+        .get_compiler();
+
+    for (env_var, name, defines) in plugins {
+        let path = out_dir.join(format!(
+            "{}{}{}",
+            env::consts::DLL_PREFIX,
+            name,
+            env::consts::DLL_SUFFIX
+        ));
+        let mut command = compiler.to_command();
+        command
+            .args(defines)
+            .args(["-shared", "-fPIC", "-o"])
+            .arg(&path)
+            .arg("c/plugin.c");
+
+        let status = command.status().expect("failed to run the C compiler");
+        assert!(status.success(), "building {} failed: {:?}", name, command);
+        println!("cargo:rustc-env={}={}", env_var, path.display());
+    }
+}
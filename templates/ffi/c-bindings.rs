@@ -0,0 +1,474 @@
+//! C bindings template: calling an existing C library from Rust
+//!
+//! Binds two libc functions available on every Linux system, `qsort` and
+//! `getaddrinfo`, the way bindgen output would: `#[repr(C)]` structs with
+//! layout assertions (mem-01, ffi-13), raw `extern "C"` declarations, and a
+//! safe wrapper per call.
+//!
+//! The declarations are written by hand to show the pattern; in production
+//! prefer the `libc` crate or generate them with bindgen.
+//!
+//! `c/c_bindings_layout.c` reports `sizeof`/`offsetof` from the real system
+//! headers, and `test_layout_matches_system_headers` compares them with the
+//! Rust definitions.
+//!
+//! Add to Cargo.toml:
+//! ```toml
+//! [build-dependencies]
+//! cc = "1"
+//! ```
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::mem::{align_of, size_of};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::{self, NonNull};
+
+// =====================================================
+// Raw Bindings (bindgen-style)
+// =====================================================
+
+#[cfg(not(target_os = "linux"))]
+compile_error!("struct layouts below are for Linux; regenerate them for other targets");
+
+// C names kept as-is, as bindgen does
+#[allow(non_camel_case_types)]
+mod sys {
+    use super::*;
+
+    pub type socklen_t = u32;
+    pub type sa_family_t = u16;
+
+    pub const AF_UNSPEC: c_int = 0;
+    pub const AF_INET: c_int = 2;
+    pub const AF_INET6: c_int = 10;
+    pub const SOCK_STREAM: c_int = 1;
+    pub const AI_NUMERICHOST: c_int = 0x0004;
+    pub const AI_NUMERICSERV: c_int = 0x0400;
+
+    /// `struct addrinfo` from <netdb.h>. Note the field order differs on
+    /// the BSDs (`ai_canonname` before `ai_addr`).
+    #[repr(C)]
+    pub struct addrinfo {
+        pub ai_flags: c_int,
+        pub ai_family: c_int,
+        pub ai_socktype: c_int,
+        pub ai_protocol: c_int,
+        pub ai_addrlen: socklen_t,
+        pub ai_addr: *mut sockaddr,
+        pub ai_canonname: *mut c_char,
+        pub ai_next: *mut addrinfo,
+    }
+
+    /// Generic socket address header; only `sa_family` is read through it.
+    #[repr(C)]
+    pub struct sockaddr {
+        pub sa_family: sa_family_t,
+        pub sa_data: [c_char; 14],
+    }
+
+    #[repr(C)]
+    pub struct sockaddr_in {
+        pub sin_family: sa_family_t,
+        /// Network byte order
+        pub sin_port: u16,
+        /// Network byte order (`struct in_addr`)
+        pub sin_addr: u32,
+        pub sin_zero: [u8; 8],
+    }
+
+    #[repr(C)]
+    pub struct sockaddr_in6 {
+        pub sin6_family: sa_family_t,
+        /// Network byte order
+        pub sin6_port: u16,
+        pub sin6_flowinfo: u32,
+        pub sin6_addr: [u8; 16],
+        pub sin6_scope_id: u32,
+    }
+
+    pub type Comparator = unsafe extern "C" fn(*const c_void, *const c_void) -> c_int;
+
+    extern "C" {
+        pub fn qsort(base: *mut c_void, nmemb: usize, size: usize, compar: Option<Comparator>);
+
+        pub fn getaddrinfo(
+            node: *const c_char,
+            service: *const c_char,
+            hints: *const addrinfo,
+            res: *mut *mut addrinfo,
+        ) -> c_int;
+        pub fn freeaddrinfo(res: *mut addrinfo);
+        pub fn gai_strerror(errcode: c_int) -> *const c_char;
+    }
+}
+
+// =====================================================
+// Layout Assertions
+// =====================================================
+
+// Checked at compile time for the target (x86_64/aarch64 Linux, LP64).
+// A mismatch here means the binding is wrong, not the C library.
+#[cfg(target_pointer_width = "64")]
+const _: () = {
+    use std::mem::offset_of;
+
+    assert!(size_of::<sys::addrinfo>() == 48);
+    assert!(align_of::<sys::addrinfo>() == 8);
+    assert!(offset_of!(sys::addrinfo, ai_addrlen) == 16);
+    assert!(offset_of!(sys::addrinfo, ai_addr) == 24);
+    assert!(offset_of!(sys::addrinfo, ai_canonname) == 32);
+    assert!(offset_of!(sys::addrinfo, ai_next) == 40);
+
+    assert!(size_of::<sys::sockaddr>() == 16);
+    assert!(size_of::<sys::sockaddr_in>() == 16);
+    assert!(align_of::<sys::sockaddr_in>() == 4);
+    assert!(offset_of!(sys::sockaddr_in, sin_addr) == 4);
+    assert!(size_of::<sys::sockaddr_in6>() == 28);
+    assert!(align_of::<sys::sockaddr_in6>() == 4);
+    assert!(offset_of!(sys::sockaddr_in6, sin6_addr) == 8);
+    assert!(offset_of!(sys::sockaddr_in6, sin6_scope_id) == 24);
+};
+
+// =====================================================
+// Safe Wrapper: qsort
+// =====================================================
+
+thread_local! {
+    // qsort has no user-data argument, so the panic is parked per thread
+    static COMPARE_PANIC: RefCell<Option<Box<dyn Any + Send>>> = const { RefCell::new(None) };
+}
+
+unsafe extern "C" fn compare<T: Ord>(a: *const c_void, b: *const c_void) -> c_int {
+    // After a panic, stop calling user code and let qsort wind down
+    if COMPARE_PANIC.with(|p| p.borrow().is_some()) {
+        return 0;
+    }
+
+    // SAFETY: qsort passes pointers to two elements of the slice we gave it
+    let (a, b) = unsafe { (&*(a as *const T), &*(b as *const T)) };
+
+    // A panic must not unwind through qsort (ffi-04): report "equal" and
+    // re-raise once qsort has returned
+    match panic::catch_unwind(AssertUnwindSafe(|| a.cmp(b))) {
+        Ok(Ordering::Less) => -1,
+        Ok(Ordering::Equal) => 0,
+        Ok(Ordering::Greater) => 1,
+        Err(payload) => {
+            COMPARE_PANIC.with(|p| *p.borrow_mut() = Some(payload));
+            0
+        }
+    }
+}
+
+/// Sort a slice in place with C's `qsort` (not stable).
+///
+/// If `T::cmp` panics, the slice is left permuted but valid and the panic
+/// is resumed after `qsort` returns. Like any C sort, this relies on the
+/// libc staying in bounds when the comparator is inconsistent.
+pub fn qsort<T: Ord>(slice: &mut [T]) {
+    if size_of::<T>() == 0 || slice.len() < 2 {
+        return;
+    }
+
+    // SAFETY: base/nmemb/size describe exactly `slice`; qsort only swaps
+    // whole elements with memcpy, which is a valid move for any Rust type
+    unsafe {
+        sys::qsort(
+            slice.as_mut_ptr() as *mut c_void,
+            slice.len(),
+            size_of::<T>(),
+            Some(compare::<T>),
+        );
+    }
+
+    if let Some(payload) = COMPARE_PANIC.with(|p| p.borrow_mut().take()) {
+        panic::resume_unwind(payload);
+    }
+}
+
+// =====================================================
+// Safe Wrapper: getaddrinfo
+// =====================================================
+
+/// Error returned by `getaddrinfo` (an `EAI_*` code).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GaiError {
+    pub code: c_int,
+    pub message: String,
+}
+
+impl fmt::Display for GaiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "getaddrinfo failed ({}): {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for GaiError {}
+
+impl GaiError {
+    fn from_code(code: c_int) -> Self {
+        // SAFETY: gai_strerror returns a static string for any code
+        let message = unsafe { CStr::from_ptr(sys::gai_strerror(code)) };
+        Self {
+            code,
+            message: message.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    Any,
+    V4,
+    V6,
+}
+
+/// Owns a list returned by `getaddrinfo`; freed with `freeaddrinfo`.
+struct AddrInfoList {
+    head: NonNull<sys::addrinfo>,
+}
+
+impl AddrInfoList {
+    fn iter(&self) -> impl Iterator<Item = &sys::addrinfo> {
+        // SAFETY: nodes live as long as the list, which we borrow
+        std::iter::successors(Some(unsafe { self.head.as_ref() }), |node| unsafe {
+            node.ai_next.as_ref()
+        })
+    }
+}
+
+impl Drop for AddrInfoList {
+    fn drop(&mut self) {
+        // SAFETY: head came from a successful getaddrinfo, freed once
+        unsafe { sys::freeaddrinfo(self.head.as_ptr()) }
+    }
+}
+
+/// Convert one `addrinfo` entry to a `SocketAddr`, skipping unknown families.
+fn to_socket_addr(info: &sys::addrinfo) -> Option<SocketAddr> {
+    if info.ai_addr.is_null() {
+        return None;
+    }
+
+    match info.ai_family {
+        sys::AF_INET if info.ai_addrlen as usize >= size_of::<sys::sockaddr_in>() => {
+            // SAFETY: family and length say this is a sockaddr_in
+            let sin = unsafe { &*(info.ai_addr as *const sys::sockaddr_in) };
+            Some(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(sin.sin_addr)),
+                u16::from_be(sin.sin_port),
+            )))
+        }
+        sys::AF_INET6 if info.ai_addrlen as usize >= size_of::<sys::sockaddr_in6>() => {
+            // SAFETY: family and length say this is a sockaddr_in6
+            let sin6 = unsafe { &*(info.ai_addr as *const sys::sockaddr_in6) };
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(sin6.sin6_addr),
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id,
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// Resolve `host` to socket addresses for TCP on `port`.
+///
+/// With `numeric_only`, `host` must be an IP literal and no DNS lookup happens.
+pub fn resolve(
+    host: &str,
+    port: u16,
+    family: Family,
+    numeric_only: bool,
+) -> Result<Vec<SocketAddr>, GaiError> {
+    let invalid = || GaiError {
+        code: 0,
+        message: "host contains a NUL byte".to_string(),
+    };
+    let c_host = CString::new(host).map_err(|_| invalid())?;
+    let c_port = CString::new(port.to_string()).expect("digits only");
+
+    // SAFETY: all-zero is a valid addrinfo (null pointers, zero ints)
+    let mut hints: sys::addrinfo = unsafe { std::mem::zeroed() };
+    hints.ai_family = match family {
+        Family::Any => sys::AF_UNSPEC,
+        Family::V4 => sys::AF_INET,
+        Family::V6 => sys::AF_INET6,
+    };
+    hints.ai_socktype = sys::SOCK_STREAM;
+    hints.ai_flags = sys::AI_NUMERICSERV | if numeric_only { sys::AI_NUMERICHOST } else { 0 };
+
+    let mut res = ptr::null_mut();
+    // SAFETY: strings are NUL-terminated, hints is initialized, res is writable
+    let code = unsafe { sys::getaddrinfo(c_host.as_ptr(), c_port.as_ptr(), &hints, &mut res) };
+    if code != 0 {
+        return Err(GaiError::from_code(code));
+    }
+
+    let Some(head) = NonNull::new(res) else {
+        return Ok(Vec::new());
+    };
+    let list = AddrInfoList { head };
+
+    let mut addrs: Vec<SocketAddr> = list.iter().filter_map(to_socket_addr).collect();
+    addrs.dedup();
+    Ok(addrs)
+}
+
+// =====================================================
+// Usage Example
+// =====================================================
+
+fn main() {
+    let mut values = vec![5, 3, 9, 1, 7];
+    qsort(&mut values);
+    println!("Sorted: {:?}", values);
+
+    match resolve("localhost", 8080, Family::Any, false) {
+        Ok(addrs) => println!("localhost: {:?}", addrs),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// =====================================================
+// Tests
+// =====================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::offset_of;
+
+    #[repr(C)]
+    struct LayoutEntry {
+        name: *const c_char,
+        value: usize,
+    }
+
+    // Exported by c/c_bindings_layout.c, compiled against system headers
+    extern "C" {
+        fn c_bindings_layout(count: *mut usize) -> *const LayoutEntry;
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls the C layout probe")]
+    fn test_layout_matches_system_headers() {
+        let rust = [
+            ("sizeof(struct addrinfo)", size_of::<sys::addrinfo>()),
+            ("alignof(struct addrinfo)", align_of::<sys::addrinfo>()),
+            ("offsetof(addrinfo, ai_flags)", offset_of!(sys::addrinfo, ai_flags)),
+            ("offsetof(addrinfo, ai_family)", offset_of!(sys::addrinfo, ai_family)),
+            ("offsetof(addrinfo, ai_socktype)", offset_of!(sys::addrinfo, ai_socktype)),
+            ("offsetof(addrinfo, ai_protocol)", offset_of!(sys::addrinfo, ai_protocol)),
+            ("offsetof(addrinfo, ai_addrlen)", offset_of!(sys::addrinfo, ai_addrlen)),
+            ("offsetof(addrinfo, ai_addr)", offset_of!(sys::addrinfo, ai_addr)),
+            ("offsetof(addrinfo, ai_canonname)", offset_of!(sys::addrinfo, ai_canonname)),
+            ("offsetof(addrinfo, ai_next)", offset_of!(sys::addrinfo, ai_next)),
+            ("sizeof(struct sockaddr)", size_of::<sys::sockaddr>()),
+            ("sizeof(struct sockaddr_in)", size_of::<sys::sockaddr_in>()),
+            ("alignof(struct sockaddr_in)", align_of::<sys::sockaddr_in>()),
+            ("offsetof(sockaddr_in, sin_port)", offset_of!(sys::sockaddr_in, sin_port)),
+            ("offsetof(sockaddr_in, sin_addr)", offset_of!(sys::sockaddr_in, sin_addr)),
+            ("sizeof(struct sockaddr_in6)", size_of::<sys::sockaddr_in6>()),
+            ("alignof(struct sockaddr_in6)", align_of::<sys::sockaddr_in6>()),
+            ("offsetof(sockaddr_in6, sin6_port)", offset_of!(sys::sockaddr_in6, sin6_port)),
+            ("offsetof(sockaddr_in6, sin6_flowinfo)", offset_of!(sys::sockaddr_in6, sin6_flowinfo)),
+            ("offsetof(sockaddr_in6, sin6_addr)", offset_of!(sys::sockaddr_in6, sin6_addr)),
+            ("offsetof(sockaddr_in6, sin6_scope_id)", offset_of!(sys::sockaddr_in6, sin6_scope_id)),
+            ("AF_INET", sys::AF_INET as usize),
+            ("AF_INET6", sys::AF_INET6 as usize),
+            ("SOCK_STREAM", sys::SOCK_STREAM as usize),
+            ("AI_NUMERICHOST", sys::AI_NUMERICHOST as usize),
+            ("AI_NUMERICSERV", sys::AI_NUMERICSERV as usize),
+        ];
+
+        let mut count = 0;
+        // SAFETY: returns a static array of `count` entries
+        let entries = unsafe {
+            let ptr = c_bindings_layout(&mut count);
+            std::slice::from_raw_parts(ptr, count)
+        };
+        assert_eq!(entries.len(), rust.len());
+
+        for (entry, (name, value)) in entries.iter().zip(rust) {
+            // SAFETY: names are static C string literals
+            let c_name = unsafe { CStr::from_ptr(entry.name) }.to_str().unwrap();
+            assert_eq!(c_name, name);
+            assert_eq!(entry.value, value, "{} differs between C and Rust", name);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls libc")]
+    fn test_qsort() {
+        let mut numbers = vec![3, -1, 42, 0, 7, 7];
+        qsort(&mut numbers);
+        assert_eq!(numbers, vec![-1, 0, 3, 7, 7, 42]);
+
+        // Non-Copy elements are moved, not duplicated or dropped
+        let mut words: Vec<String> = ["pear", "apple", "fig"].map(String::from).to_vec();
+        qsort(&mut words);
+        assert_eq!(words, ["apple", "fig", "pear"]);
+
+        let mut empty: Vec<u8> = vec![];
+        qsort(&mut empty);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls libc")]
+    fn test_qsort_comparator_panic_is_resumed() {
+        #[derive(PartialEq, Eq)]
+        struct Poison(u32);
+        impl PartialOrd for Poison {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Poison {
+            fn cmp(&self, other: &Self) -> Ordering {
+                if self.0 == 13 || other.0 == 13 {
+                    panic!("unlucky");
+                }
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let mut items: Vec<Poison> = [5, 13, 2, 8].into_iter().map(Poison).collect();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| qsort(&mut items)));
+
+        assert!(result.is_err());
+        // A permutation of the input: nothing lost or duplicated mid-swap
+        let mut values: Vec<u32> = items.iter().map(|item| item.0).collect();
+        values.sort_unstable();
+        assert_eq!(values, [2, 5, 8, 13]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls libc")]
+    fn test_resolve_numeric() {
+        let v4 = resolve("127.0.0.1", 80, Family::Any, true).unwrap();
+        assert_eq!(v4, vec!["127.0.0.1:80".parse::<SocketAddr>().unwrap()]);
+
+        let v6 = resolve("::1", 443, Family::V6, true).unwrap();
+        assert_eq!(v6, vec!["[::1]:443".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls libc")]
+    fn test_resolve_errors() {
+        let err = resolve("not-an-ip", 80, Family::Any, true).unwrap_err();
+        assert_ne!(err.code, 0);
+        assert!(!err.message.is_empty());
+
+        // Family mismatch is reported by getaddrinfo, not silently empty
+        assert!(resolve("127.0.0.1", 80, Family::V6, true).is_err());
+        assert!(resolve("a\0b", 80, Family::Any, true).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+/*
+ * c_bindings_layout.c - layout facts from the system headers, compared
+ * against the Rust definitions in c-bindings.rs by its tests.
+ *
+ * Entries must stay in the same order as the table in
+ * test_layout_matches_system_headers.
+ */
+#define _POSIX_C_SOURCE 200112L
+
+#include <netdb.h>
+#include <netinet/in.h>
+#include <stdalign.h>
+#include <stddef.h>
+#include <sys/socket.h>
+
+struct layout_entry {
+    const char *name;
+    size_t value;
+};
+
+#define SIZE(t) {"sizeof(struct " #t ")", sizeof(struct t)}
+#define ALIGN(t) {"alignof(struct " #t ")", alignof(struct t)}
+#define OFFSET(t, f) {"offsetof(" #t ", " #f ")", offsetof(struct t, f)}
+#define CONSTANT(c) {#c, (size_t)(c)}
+
+static const struct layout_entry entries[] = {
+    SIZE(addrinfo),
+    ALIGN(addrinfo),
+    OFFSET(addrinfo, ai_flags),
+    OFFSET(addrinfo, ai_family),
+    OFFSET(addrinfo, ai_socktype),
+    OFFSET(addrinfo, ai_protocol),
+    OFFSET(addrinfo, ai_addrlen),
+    OFFSET(addrinfo, ai_addr),
+    OFFSET(addrinfo, ai_canonname),
+    OFFSET(addrinfo, ai_next),
+    SIZE(sockaddr),
+    SIZE(sockaddr_in),
+    ALIGN(sockaddr_in),
+    OFFSET(sockaddr_in, sin_port),
+    OFFSET(sockaddr_in, sin_addr),
+    SIZE(sockaddr_in6),
+    ALIGN(sockaddr_in6),
+    OFFSET(sockaddr_in6, sin6_port),
+    OFFSET(sockaddr_in6, sin6_flowinfo),
+    OFFSET(sockaddr_in6, sin6_addr),
+    OFFSET(sockaddr_in6, sin6_scope_id),
+    CONSTANT(AF_INET),
+    CONSTANT(AF_INET6),
+    CONSTANT(SOCK_STREAM),
+    CONSTANT(AI_NUMERICHOST),
+    CONSTANT(AI_NUMERICSERV),
+};
+
+const struct layout_entry *c_bindings_layout(size_t *count) {
+    *count = sizeof entries / sizeof entries[0];
+    return entries;
+}
@@ -0,0 +1,109 @@
+/* Generated with cbindgen from expose-api.rs. Do not edit by hand. */
+
+#ifndef EXPOSE_API_H
+#define EXPOSE_API_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define STORE_OK 0
+
+#define STORE_ERR_NULL_ARGUMENT -1
+
+#define STORE_ERR_INVALID_UTF8 -2
+
+#define STORE_ERR_INVALID_KEY -3
+
+#define STORE_ERR_NOT_FOUND -4
+
+#define STORE_ERR_BUFFER_TOO_SMALL -5
+
+#define STORE_ERR_PANIC -99
+
+/**
+ * Simple in-memory key/value store; opaque to C.
+ */
+typedef struct Store Store;
+
+/**
+ * Create a store. Returns NULL on failure (see `store_last_error`).
+ */
+Store *store_new(void);
+
+/**
+ * Destroy a store. NULL is a no-op.
+ *
+ * # Safety
+ * `store` must be NULL or a handle from `store_new` not yet freed.
+ */
+void store_free(Store *store);
+
+/**
+ * Insert or replace `key`. Both strings are copied.
+ *
+ * # Safety
+ * `store` must be a live handle; `key` and `value` NUL-terminated strings.
+ */
+int store_set(Store *store, const char *key, const char *value);
+
+/**
+ * Look up `key` and return a newly allocated copy in `*out`.
+ * Free it with `store_string_free`.
+ *
+ * # Safety
+ * `store` must be a live handle, `key` a NUL-terminated string and `out`
+ * a valid pointer to write to.
+ */
+int store_get(const Store *store, const char *key, char **out);
+
+/**
+ * Copy the value for `key` into a caller-provided buffer, NUL-terminated.
+ *
+ * `*required` is always set to the needed size including the NUL, so a
+ * caller can retry with a larger buffer after `STORE_ERR_BUFFER_TOO_SMALL`.
+ *
+ * # Safety
+ * `buf` must be valid for `buf_len` bytes (or NULL with `buf_len` 0);
+ * `required` must be a valid pointer to write to.
+ */
+int store_get_into(const Store *store,
+                   const char *key,
+                   char *buf,
+                   size_t buf_len,
+                   size_t *required);
+
+/**
+ * Serialize all entries as `key=value\n` lines into a new buffer.
+ * Free it with `store_buffer_free(*out, *out_len)`.
+ *
+ * # Safety
+ * `store` must be a live handle; `out` and `out_len` valid pointers.
+ */
+int store_export(const Store *store, uint8_t **out, size_t *out_len);
+
+/**
+ * Free a string returned by `store_get`. NULL is a no-op.
+ *
+ * # Safety
+ * `s` must be NULL or a pointer from `store_get` not yet freed.
+ */
+void store_string_free(char *s);
+
+/**
+ * Free a buffer returned by `store_export`. NULL is a no-op.
+ *
+ * # Safety
+ * `buf`/`len` must be exactly the pair returned by `store_export`.
+ */
+void store_buffer_free(uint8_t *buf, size_t len);
+
+/**
+ * Message for the last failed call on this thread, or NULL.
+ * Valid until the next `store_*` call on the same thread; do not free.
+ */
+const char *store_last_error(void);
+
+#endif /* EXPOSE_API_H */
@@ -0,0 +1,104 @@
+/*
+ * expose_api_test.c - drives the API from expose-api.rs the way a C
+ * consumer would, using only expose_api.h.
+ *
+ * Linked into the Rust test binary by build.rs (see test_c_program). To run
+ * it standalone against the built static library:
+ *
+ *   cc -DEXPOSE_API_STANDALONE c/expose_api_test.c -Ic \
+ *      target/debug/libexpose_api.a -lpthread -ldl -lm -o expose_api_test
+ */
+#include "expose_api.h"
+
+#include <stdio.h>
+#include <string.h>
+
+static int failures;
+
+#define CHECK(cond)                                                          \
+    do {                                                                     \
+        if (!(cond)) {                                                       \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+                    #cond);                                                  \
+            failures++;                                                      \
+        }                                                                    \
+    } while (0)
+
+static void test_owned_string(Store *store) {
+    char *value = NULL;
+    CHECK(store_get(store, "name", &value) == STORE_OK);
+    CHECK(value != NULL && strcmp(value, "ferris") == 0);
+    /* Rust allocated it, Rust frees it */
+    store_string_free(value);
+}
+
+static void test_errors(Store *store) {
+    char *value = NULL;
+    CHECK(store_get(store, "missing", &value) == STORE_ERR_NOT_FOUND);
+    CHECK(value == NULL);
+    CHECK(store_last_error() != NULL);
+    CHECK(strstr(store_last_error(), "missing") != NULL);
+
+    CHECK(store_set(store, NULL, "x") == STORE_ERR_NULL_ARGUMENT);
+    CHECK(store_set(NULL, "k", "x") == STORE_ERR_NULL_ARGUMENT);
+    CHECK(store_set(store, "\xff\xfe", "x") == STORE_ERR_INVALID_UTF8);
+    CHECK(store_set(store, "", "x") == STORE_ERR_INVALID_KEY);
+
+    /* Success clears the last error */
+    CHECK(store_set(store, "k", "v") == STORE_OK);
+    CHECK(store_last_error() == NULL);
+}
+
+static void test_caller_buffer(Store *store) {
+    char small[4];
+    char large[16];
+    size_t required = 0;
+
+    CHECK(store_get_into(store, "name", small, sizeof small, &required) ==
+          STORE_ERR_BUFFER_TOO_SMALL);
+    CHECK(required == sizeof "ferris");
+
+    /* Size query with no buffer */
+    CHECK(store_get_into(store, "name", NULL, 0, &required) ==
+          STORE_ERR_BUFFER_TOO_SMALL);
+
+    CHECK(store_get_into(store, "name", large, sizeof large, &required) ==
+          STORE_OK);
+    CHECK(strcmp(large, "ferris") == 0);
+}
+
+static void test_export(Store *store) {
+    const char expected[] = "k=v\nname=ferris\n";
+    uint8_t *buf = NULL;
+    size_t len = 0;
+
+    CHECK(store_export(store, &buf, &len) == STORE_OK);
+    CHECK(len == strlen(expected));
+    CHECK(buf != NULL && memcmp(buf, expected, len) == 0);
+    store_buffer_free(buf, len);
+}
+
+int expose_api_c_tests(void) {
+    failures = 0;
+
+    Store *store = store_new();
+    CHECK(store != NULL);
+    CHECK(store_set(store, "name", "ferris") == STORE_OK);
+
+    test_owned_string(store);
+    test_errors(store);
+    test_caller_buffer(store);
+    test_export(store);
+
+    store_free(store);
+    store_free(NULL);
+    return failures;
+}
+
+#ifdef EXPOSE_API_STANDALONE
+int main(void) {
+    int failed = expose_api_c_tests();
+    printf("expose_api: %s\n", failed == 0 ? "ok" : "FAILED");
+    return failed == 0 ? 0 : 1;
+}
+#endif
@@ -0,0 +1,497 @@
+/*
+ * mocklib.c - in-tree implementation of mocklib.h for tests
+ *
+ * lib_process() behaviour is selected by the input string:
+ *   "fail"           -> returns LIB_EFAIL, error message set
+ *   "fail-bad-utf8"  -> returns LIB_EFAIL, error message is not valid UTF-8
+ *   "fail-io"        -> returns LIB_EERRNO with errno = EIO
+ *   "fail-unknown"   -> returns 42, a code this header does not define
+ *   "null-result"    -> returns 0, lib_get_result() returns NULL
+ *   "bad-utf8"       -> returns 0, result is not valid UTF-8
+ *   anything else    -> returns 0, result is the upper-cased input (or
+ *                       lower-cased with option mode=lower)
+ */
+#define _POSIX_C_SOURCE 200809L
+
+#include "mocklib.h"
+
+#include <ctype.h>
+#include <errno.h>
+#include <pthread.h>
+#include <stdatomic.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+
+struct lib_handle {
+    char *result;
+    lib_point *points;
+    size_t point_count;
+    lib_callback callback;
+    void *user_data;
+
+    /* Copied from lib_config */
+    char name[64];
+    unsigned int max_input_len;
+    unsigned int timeout_ms;
+    int lowercase;
+    size_t option_count;
+
+    /* Concurrency detection */
+    atomic_int in_process;
+    atomic_int violations;
+    atomic_int in_hash;
+    atomic_int max_hash_concurrency;
+};
+
+static _Thread_local char last_error[256];
+static _Thread_local int has_error;
+static _Thread_local int fail_next_create;
+/* Per thread, so only meaningful when the same thread creates and frees */
+static _Thread_local int live_handles;
+static _Thread_local int outstanding_allocs;
+static atomic_int pending_async;
+
+/* Widens the window in which overlapping calls can be observed */
+static void pause_briefly(void) {
+    struct timespec ts = {0, 200 * 1000};
+    nanosleep(&ts, NULL);
+}
+
+static void set_error(const char *message) {
+    snprintf(last_error, sizeof last_error, "%s", message);
+    has_error = 1;
+}
+
+/* strdup() is POSIX, not C11 */
+static char *copy_string(const char *s) {
+    size_t len = strlen(s);
+    char *copy = malloc(len + 1);
+    if (copy != NULL) {
+        memcpy(copy, s, len + 1);
+    }
+    return copy;
+}
+
+static void set_result(lib_handle *handle, char *result) {
+    free(handle->result);
+    handle->result = result;
+}
+
+lib_handle *lib_create(void) {
+    if (fail_next_create) {
+        fail_next_create = 0;
+        set_error("create failed (injected)");
+        return NULL;
+    }
+
+    lib_handle *handle = calloc(1, sizeof *handle);
+    if (handle == NULL) {
+        set_error("out of memory");
+        return NULL;
+    }
+    live_handles++;
+    return handle;
+}
+
+lib_handle *lib_create_with_config(const lib_config *config) {
+    if (config == NULL) {
+        set_error("config is null");
+        return NULL;
+    }
+    if (config->struct_size < sizeof(lib_config)) {
+        set_error("config struct_size too small");
+        return NULL;
+    }
+    if (config->name != NULL && strlen(config->name) >= sizeof(((lib_handle *)0)->name)) {
+        set_error("name too long");
+        return NULL;
+    }
+
+    int lowercase = 0;
+    for (size_t i = 0; i < config->option_count; i++) {
+        const lib_option *option = &config->options[i];
+        if (option->key == NULL || option->value == NULL) {
+            set_error("option key or value is null");
+            return NULL;
+        }
+        if (strcmp(option->key, "mode") == 0) {
+            if (strcmp(option->value, "lower") == 0) {
+                lowercase = 1;
+            } else if (strcmp(option->value, "upper") != 0) {
+                set_error("invalid value for option: mode");
+                return NULL;
+            }
+        } else {
+            char message[128];
+            snprintf(message, sizeof message, "unknown option: %s", option->key);
+            set_error(message);
+            return NULL;
+        }
+    }
+
+    lib_handle *handle = lib_create();
+    if (handle == NULL) {
+        return NULL;
+    }
+    if (config->name != NULL) {
+        snprintf(handle->name, sizeof handle->name, "%s", config->name);
+    }
+    handle->max_input_len = config->max_input_len;
+    handle->timeout_ms = config->timeout_ms;
+    handle->lowercase = lowercase;
+    handle->option_count = config->option_count;
+    return handle;
+}
+
+void lib_destroy(lib_handle *handle) {
+    if (handle == NULL) {
+        return;
+    }
+    free(handle->result);
+    free(handle->points);
+    free(handle);
+    live_handles--;
+}
+
+static int process_locked(lib_handle *handle, const char *input);
+
+int lib_process(lib_handle *handle, const char *input) {
+    if (handle == NULL || input == NULL) {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+
+    if (atomic_fetch_add(&handle->in_process, 1) != 0) {
+        atomic_fetch_add(&handle->violations, 1);
+        atomic_fetch_sub(&handle->in_process, 1);
+        set_error("concurrent call on non-reentrant handle");
+        return LIB_EBUSY;
+    }
+    pause_briefly();
+    int rc = process_locked(handle, input);
+    atomic_fetch_sub(&handle->in_process, 1);
+    return rc;
+}
+
+static int process_locked(lib_handle *handle, const char *input) {
+    if (strcmp(input, "fail") == 0) {
+        set_error("processing failed: input rejected");
+        return LIB_EFAIL;
+    }
+    if (strcmp(input, "fail-bad-utf8") == 0) {
+        set_error("bad \xff\xfe message");
+        return LIB_EFAIL;
+    }
+    if (strcmp(input, "fail-io") == 0) {
+        set_error("write to backing store failed");
+        errno = EIO; /* last, so nothing above can overwrite it */
+        return LIB_EERRNO;
+    }
+    if (strcmp(input, "fail-unknown") == 0) {
+        set_error("code from a newer library version");
+        return 42;
+    }
+    if (strcmp(input, "null-result") == 0) {
+        set_result(handle, NULL);
+        return 0;
+    }
+    if (strcmp(input, "bad-utf8") == 0) {
+        set_result(handle, copy_string("\xc3\x28"));
+        return 0;
+    }
+
+    if (handle->max_input_len != 0 && strlen(input) > handle->max_input_len) {
+        set_error("input too long");
+        return LIB_ETOOLONG;
+    }
+
+    char *result = copy_string(input);
+    if (result == NULL) {
+        set_error("out of memory");
+        return LIB_ENOMEM;
+    }
+    for (char *p = result; *p != '\0'; p++) {
+        unsigned char c = (unsigned char)*p;
+        *p = (char)(handle->lowercase ? tolower(c) : toupper(c));
+    }
+    set_result(handle, result);
+    return 0;
+}
+
+int lib_hash(lib_handle *handle, const char *input, unsigned long long *out) {
+    if (handle == NULL || input == NULL || out == NULL) {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+
+    int now = atomic_fetch_add(&handle->in_hash, 1) + 1;
+    int peak = atomic_load(&handle->max_hash_concurrency);
+    while (now > peak &&
+           !atomic_compare_exchange_weak(&handle->max_hash_concurrency, &peak, now)) {
+    }
+    pause_briefly();
+
+    unsigned long long hash = 0xcbf29ce484222325ULL;
+    for (const unsigned char *p = (const unsigned char *)input; *p != '\0'; p++) {
+        hash = (hash ^ *p) * 0x100000001b3ULL;
+    }
+    *out = hash;
+
+    atomic_fetch_sub(&handle->in_hash, 1);
+    return 0;
+}
+
+const char *lib_get_result(lib_handle *handle) {
+    return handle == NULL ? NULL : handle->result;
+}
+
+int lib_get_name(lib_handle *handle, char *buf, size_t *len) {
+    if (handle == NULL || len == NULL) {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    size_t required = strlen(handle->name) + 1;
+    if (buf == NULL || *len < required) {
+        *len = required;
+        return LIB_ERANGE;
+    }
+    memcpy(buf, handle->name, required);
+    *len = required;
+    return 0;
+}
+
+int lib_copy_result(lib_handle *handle, char *buf, size_t len) {
+    if (handle == NULL || buf == NULL) {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    if (handle->result == NULL) {
+        set_error("no result available");
+        return LIB_EFAIL;
+    }
+    size_t required = strlen(handle->result) + 1;
+    if (len < required) {
+        return LIB_ERANGE;
+    }
+    memcpy(buf, handle->result, required);
+    return 0;
+}
+
+int lib_format_result(lib_handle *handle, char *buf, size_t len) {
+    if (handle == NULL || (buf == NULL && len != 0)) {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    if (handle->result == NULL) {
+        set_error("no result available");
+        return LIB_EFAIL;
+    }
+    return snprintf(buf, len, "result=%s", handle->result);
+}
+
+int64_t lib_sum(const int32_t *values, size_t len) {
+    int64_t total = 0;
+    for (size_t i = 0; i < len; i++) {
+        total += values[i];
+    }
+    return total;
+}
+
+void lib_scale(int32_t *values, size_t len, int32_t factor) {
+    for (size_t i = 0; i < len; i++) {
+        values[i] = (int32_t)((uint32_t)values[i] * (uint32_t)factor);
+    }
+}
+
+int lib_translate(lib_point *points, int count, double dx, double dy) {
+    if (count < 0 || (points == NULL && count > 0)) {
+        set_error("invalid point array");
+        return LIB_EINVAL;
+    }
+    for (int i = 0; i < count; i++) {
+        points[i].x += dx;
+        points[i].y += dy;
+    }
+    return LIB_OK;
+}
+
+int lib_set_points(lib_handle *handle, const lib_point *points, size_t count) {
+    if (handle == NULL || (points == NULL && count > 0)) {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    lib_point *copy = NULL;
+    if (count > 0) {
+        copy = malloc(count * sizeof *copy);
+        if (copy == NULL) {
+            set_error("out of memory");
+            return LIB_ENOMEM;
+        }
+        memcpy(copy, points, count * sizeof *copy);
+    }
+    free(handle->points);
+    handle->points = copy;
+    handle->point_count = count;
+    return LIB_OK;
+}
+
+const lib_point *lib_get_points(lib_handle *handle, size_t *count) {
+    if (handle == NULL || count == NULL) {
+        return NULL;
+    }
+    *count = handle->point_count;
+    return handle->points;
+}
+
+char *lib_describe(lib_handle *handle) {
+    if (handle == NULL) {
+        set_error("null argument");
+        return NULL;
+    }
+    const char *result = handle->result != NULL ? handle->result : "";
+    int needed = snprintf(NULL, 0, "handle '%s', last result '%s'", handle->name, result);
+    char *description = malloc((size_t)needed + 1);
+    if (description == NULL) {
+        set_error("out of memory");
+        return NULL;
+    }
+    snprintf(description, (size_t)needed + 1, "handle '%s', last result '%s'",
+             handle->name, result);
+    outstanding_allocs++;
+    return description;
+}
+
+void lib_free(void *ptr) {
+    if (ptr != NULL) {
+        outstanding_allocs--;
+        free(ptr);
+    }
+}
+
+const char *lib_get_error(void) {
+    return has_error ? last_error : NULL;
+}
+
+void lib_set_callback(lib_handle *handle, lib_callback callback, void *user_data) {
+    if (handle == NULL) {
+        return;
+    }
+    handle->callback = callback;
+    handle->user_data = callback == NULL ? NULL : user_data;
+}
+
+int lib_emit(lib_handle *handle, int count) {
+    if (handle == NULL || handle->callback == NULL) {
+        set_error("no callback registered");
+        return LIB_EINVAL;
+    }
+    for (int i = 0; i < count; i++) {
+        handle->callback(handle->user_data, i);
+    }
+    return count;
+}
+
+typedef struct async_job {
+    char *input;
+    int lowercase;
+    lib_completion completion;
+    void *user_data;
+} async_job;
+
+static void *run_async_job(void *arg) {
+    async_job *job = arg;
+    pause_briefly();
+
+    if (strcmp(job->input, "fail") == 0) {
+        job->completion(job->user_data, LIB_EFAIL, NULL, "processing failed: input rejected");
+    } else {
+        for (char *p = job->input; *p != '\0'; p++) {
+            unsigned char c = (unsigned char)*p;
+            *p = (char)(job->lowercase ? tolower(c) : toupper(c));
+        }
+        job->completion(job->user_data, LIB_OK, job->input, NULL);
+    }
+
+    free(job->input);
+    free(job);
+    atomic_fetch_sub(&pending_async, 1);
+    return NULL;
+}
+
+int lib_process_async(lib_handle *handle, const char *input, lib_completion completion,
+                      void *user_data) {
+    if (handle == NULL || input == NULL || completion == NULL) {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+
+    async_job *job = malloc(sizeof *job);
+    char *copy = copy_string(input);
+    if (job == NULL || copy == NULL) {
+        free(job);
+        free(copy);
+        set_error("out of memory");
+        return LIB_ENOMEM;
+    }
+    job->input = copy;
+    job->lowercase = handle->lowercase;
+    job->completion = completion;
+    job->user_data = user_data;
+
+    atomic_fetch_add(&pending_async, 1);
+    pthread_t thread;
+    if (pthread_create(&thread, NULL, run_async_job, job) != 0) {
+        atomic_fetch_sub(&pending_async, 1);
+        free(copy);
+        free(job);
+        set_error("failed to start worker thread");
+        return LIB_EFAIL;
+    }
+    pthread_detach(thread);
+    return LIB_OK;
+}
+
+void mock_fail_next_create(void) {
+    fail_next_create = 1;
+}
+
+int mock_live_handles(void) {
+    return live_handles;
+}
+
+int mock_outstanding_allocs(void) {
+    return outstanding_allocs;
+}
+
+int mock_pending_async(void) {
+    return atomic_load(&pending_async);
+}
+
+const void *mock_get_points_misaligned(lib_handle *handle, size_t *count) {
+    const lib_point *points = lib_get_points(handle, count);
+    return points == NULL ? NULL : (const char *)points + 1;
+}
+
+size_t mock_sizeof_point(void) {
+    return sizeof(lib_point);
+}
+
+void mock_config_summary(lib_handle *handle, char *buf, size_t len) {
+    snprintf(buf, len, "name=%s max_input_len=%u timeout_ms=%u options=%zu",
+             handle->name, handle->max_input_len, handle->timeout_ms,
+             handle->option_count);
+}
+
+size_t mock_sizeof_config(void) {
+    return sizeof(lib_config);
+}
+
+int mock_handle_violations(lib_handle *handle) {
+    return atomic_load(&handle->violations);
+}
+
+int mock_handle_max_concurrency(lib_handle *handle) {
+    return atomic_load(&handle->max_hash_concurrency);
+}
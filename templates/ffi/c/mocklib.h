@@ -0,0 +1,187 @@
+/*
+ * mocklib.h - API of the C library wrapped by safe-wrapper.rs
+ *
+ * A stand-in for a real vendor library: an opaque handle, an int status
+ * code and a thread-local last-error string.
+ */
+#ifndef MOCKLIB_H
+#define MOCKLIB_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct lib_handle lib_handle;
+
+/*
+ * Status codes returned by int functions. Every failure also sets the
+ * thread-local lib_get_error() message; LIB_EERRNO additionally leaves the
+ * cause in errno.
+ */
+#define LIB_OK 0
+#define LIB_EFAIL (-1)    /* operation failed */
+#define LIB_ERANGE (-2)   /* caller-provided buffer too small */
+#define LIB_EINVAL (-3)   /* null or invalid argument */
+#define LIB_EBUSY (-4)    /* overlapping call on a non-reentrant handle */
+#define LIB_ETOOLONG (-5) /* input longer than max_input_len */
+#define LIB_ENOMEM (-6)   /* allocation failed */
+#define LIB_EERRNO (-7)   /* system call failed, see errno */
+
+typedef struct lib_option {
+    const char *key;
+    const char *value;
+} lib_option;
+
+/*
+ * Creation options. Callers set struct_size to sizeof(lib_config) so the
+ * library can tell which fields an older caller knows about.
+ *
+ * Known option keys:
+ *   "mode"  "upper" (default) or "lower"
+ */
+typedef struct lib_config {
+    size_t struct_size;
+    const char *name;           /* optional, may be NULL */
+    unsigned int max_input_len; /* 0 = unlimited */
+    unsigned int timeout_ms;    /* 0 = library default */
+    const lib_option *options;  /* option_count entries, may be NULL if 0 */
+    size_t option_count;
+} lib_config;
+
+/* Returns NULL on failure. */
+lib_handle *lib_create(void);
+
+/* Like lib_create(); the config is copied. Returns NULL if it is rejected. */
+lib_handle *lib_create_with_config(const lib_config *config);
+void lib_destroy(lib_handle *handle);
+
+/*
+ * Thread safety: a handle may be used from any thread, but calls on one
+ * handle must not overlap, except for functions marked reentrant. Distinct
+ * handles are independent. Errors are reported per thread.
+ */
+
+/* Returns LIB_OK or one of the LIB_E* codes. Not reentrant. */
+int lib_process(lib_handle *handle, const char *input);
+
+/*
+ * FNV-1a hash of input into *out. Reentrant: may be called concurrently on
+ * the same handle (reads only state fixed at creation).
+ */
+int lib_hash(lib_handle *handle, const char *input, unsigned long long *out);
+
+/* Borrowed from the handle, valid until the next call on it. May be NULL. */
+const char *lib_get_result(lib_handle *handle);
+
+/*
+ * Size-query convention: the configured name is copied into buf. If buf is
+ * NULL or *len is too small, *len is set to the required size (including
+ * the NUL) and LIB_ERANGE is returned.
+ */
+int lib_get_name(lib_handle *handle, char *buf, size_t *len);
+
+/*
+ * Retry convention (like getcwd): copies the last result into buf, or
+ * returns LIB_ERANGE without saying how much room is needed.
+ */
+int lib_copy_result(lib_handle *handle, char *buf, size_t len);
+
+/*
+ * snprintf convention: writes "result=<last result>" truncated to len - 1
+ * chars plus a NUL, and returns the untruncated length. Returns LIB_EINVAL
+ * for a null argument and LIB_EFAIL if there is no result yet.
+ */
+int lib_format_result(lib_handle *handle, char *buf, size_t len);
+
+/*
+ * Arrays. Pointers may be NULL only when the count is 0, and are only used
+ * during the call unless stated otherwise.
+ */
+typedef struct lib_point {
+    double x;
+    double y;
+    int32_t weight;
+} lib_point;
+
+/* Sum of len values; never overflows for len < 2^32. */
+int64_t lib_sum(const int32_t *values, size_t len);
+
+/* Multiplies every value in place, wrapping on overflow. */
+void lib_scale(int32_t *values, size_t len, int32_t factor);
+
+/* Moves every point in place. Legacy API: the count is an int. */
+int lib_translate(lib_point *points, int count, double dx, double dy);
+
+/* Copies count points into the handle, replacing the previous set. */
+int lib_set_points(lib_handle *handle, const lib_point *points, size_t count);
+
+/* Points owned by the handle, valid until lib_set_points() or lib_destroy(). */
+const lib_point *lib_get_points(lib_handle *handle, size_t *count);
+
+/* Newly allocated description; release with lib_free(), never free(). */
+char *lib_describe(lib_handle *handle);
+void lib_free(void *ptr);
+
+/* Last error on the calling thread, or NULL. */
+const char *lib_get_error(void);
+
+typedef void (*lib_callback)(void *user_data, int value);
+
+/* Replaces any previous callback; pass NULL to unregister. */
+void lib_set_callback(lib_handle *handle, lib_callback callback, void *user_data);
+
+/*
+ * Delivers events 0..count-1 to the registered callback, synchronously.
+ * Returns the number delivered, or LIB_EINVAL if handle is NULL or no
+ * callback is registered.
+ * Callbacks are only ever invoked from inside this function.
+ */
+int lib_emit(lib_handle *handle, int count);
+
+/*
+ * Completion for lib_process_async(). Called exactly once, on a library
+ * thread. result (on LIB_OK) and error (otherwise) are only valid during
+ * the call; lib_get_error() is not set, as it belongs to the caller's thread.
+ */
+typedef void (*lib_completion)(void *user_data, int status, const char *result,
+                               const char *error);
+
+/*
+ * Processes input on a library-owned thread. Returns LIB_OK once the work
+ * is queued; otherwise completion is never called. The handle and input
+ * are only used during this call. Not reentrant.
+ */
+int lib_process_async(lib_handle *handle, const char *input, lib_completion completion,
+                      void *user_data);
+
+/*
+ * Test hooks (not part of a real library API)
+ *
+ * Injection flags and counters are thread-local so parallel tests do not
+ * interfere with each other. A handle or allocation is counted on the thread
+ * that created it and uncounted on the thread that freed it, so a test that
+ * checks a counter must create and free on its own thread; handles moved to
+ * an AsyncLibrary worker or shared across threads skew both threads' counts.
+ */
+void mock_fail_next_create(void);
+int mock_live_handles(void);
+/* Allocations returned by lib_describe() not yet passed to lib_free(). */
+int mock_outstanding_allocs(void);
+/* lib_process_async() jobs whose completion has not returned yet (all threads). */
+int mock_pending_async(void);
+/* lib_get_points(), but one byte past the real array: misaligned on purpose. */
+const void *mock_get_points_misaligned(lib_handle *handle, size_t *count);
+size_t mock_sizeof_point(void);
+
+/* Writes a summary of the handle's effective config into buf. */
+void mock_config_summary(lib_handle *handle, char *buf, size_t len);
+size_t mock_sizeof_config(void);
+
+/*
+ * Overlapping lib_process() calls on a handle. Instead of corrupting state
+ * the mock fails the overlapping call, so the detector itself is testable.
+ */
+int mock_handle_violations(lib_handle *handle);
+/* Peak number of concurrent lib_hash() calls seen on a handle. */
+int mock_handle_max_concurrency(lib_handle *handle);
+
+#endif /* MOCKLIB_H */
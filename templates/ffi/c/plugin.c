@@ -0,0 +1,42 @@
+/*
+ * Small plugin loaded at runtime by dynamic-loading.rs.
+ *
+ * build.rs compiles it into three shared objects:
+ *   - the current plugin
+ *   - one built with -DPLUGIN_ABI_VERSION=1 (an outdated plugin)
+ *   - one built with -DPLUGIN_MINIMAL (missing plugin_reverse)
+ */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifndef PLUGIN_ABI_VERSION
+#define PLUGIN_ABI_VERSION 2
+#endif
+
+/* Checked by the host before any function is resolved. */
+const uint32_t plugin_abi_version = PLUGIN_ABI_VERSION;
+
+/* Static string: valid for as long as the library stays loaded. */
+const char *plugin_name(void) {
+    return "reverser";
+}
+
+/* Wraps on overflow instead of invoking signed-overflow UB. */
+int32_t plugin_add(int32_t a, int32_t b) {
+    return (int32_t)((uint32_t)a + (uint32_t)b);
+}
+
+#ifndef PLUGIN_MINIMAL
+/* Reverses len bytes of buf in place. */
+void plugin_reverse(unsigned char *buf, size_t len) {
+    if (buf == NULL || len == 0) {
+        return;
+    }
+    for (size_t i = 0, j = len - 1; i < j; i++, j--) {
+        unsigned char tmp = buf[i];
+        buf[i] = buf[j];
+        buf[j] = tmp;
+    }
+}
+#endif
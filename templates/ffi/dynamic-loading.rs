@@ -0,0 +1,273 @@
+//! Dynamic loading template: opening a C library at runtime
+//!
+//! Link-time binding (`extern "C"` + `build.rs`) needs the library when the
+//! crate is built. Plugins and optional dependencies are opened at runtime
+//! instead: `dlopen` the shared object, check its ABI version, then resolve
+//! every function into a typed vtable up front.
+//!
+//! Every resolved symbol borrows the loaded library, so the borrow checker
+//! rejects any use after the library is closed.
+//!
+//! The plugin is `c/plugin.c`, built into shared objects by `build.rs`.
+//!
+//! Add to Cargo.toml:
+//! ```toml
+//! [dependencies]
+//! libloading = "0.8"
+//! thiserror = "1"
+//!
+//! [build-dependencies]
+//! cc = "1"
+//! ```
+
+use libloading::{Library, Symbol};
+use std::ffi::{c_char, CStr};
+use std::path::{Path, PathBuf};
+
+// =====================================================
+// Plugin ABI
+// =====================================================
+
+// Mirrors c/plugin.c. Bump ABI_VERSION together with PLUGIN_ABI_VERSION
+// whenever a signature below changes.
+
+/// ABI version this host was written against.
+pub const ABI_VERSION: u32 = 2;
+
+type NameFn = unsafe extern "C" fn() -> *const c_char;
+type AddFn = unsafe extern "C" fn(a: i32, b: i32) -> i32;
+type ReverseFn = unsafe extern "C" fn(buf: *mut u8, len: usize);
+
+// =====================================================
+// Error Type
+// =====================================================
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to load {}", path.display())]
+    Open {
+        path: PathBuf,
+        source: libloading::Error,
+    },
+
+    #[error("missing symbol `{symbol}`")]
+    MissingSymbol {
+        symbol: &'static str,
+        source: libloading::Error,
+    },
+
+    #[error("plugin ABI version {found} does not match host version {expected}")]
+    AbiMismatch { found: u32, expected: u32 },
+
+    #[error("failed to unload plugin")]
+    Close(#[source] libloading::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// =====================================================
+// Loaded Library
+// =====================================================
+
+/// An opened plugin whose ABI version has been checked.
+///
+/// Dropping it unloads the shared object; `api()` borrows it, so nothing
+/// resolved from it can be used afterwards.
+pub struct Plugin {
+    lib: Library,
+}
+
+impl Plugin {
+    /// Open the shared object at `path` and check its ABI version.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        // SAFETY: loading runs the library's initializers. The path must
+        // name a trusted plugin built from c/plugin.c; there is no way to
+        // verify that from here, which is why the ABI symbol is checked next.
+        let lib = unsafe { Library::new(path) }.map_err(|source| Error::Open {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let plugin = Plugin { lib };
+        let found = plugin.abi_version()?;
+        if found != ABI_VERSION {
+            return Err(Error::AbiMismatch {
+                found,
+                expected: ABI_VERSION,
+            });
+        }
+        Ok(plugin)
+    }
+
+    fn abi_version(&self) -> Result<u32> {
+        // SAFETY: plugin_abi_version is declared `const uint32_t` in every
+        // ABI version, so the symbol is the address of a u32
+        let version: Symbol<'_, *const u32> = unsafe { self.symbol("plugin_abi_version")? };
+
+        // SAFETY: the address is valid and aligned for as long as the library
+        // is loaded, and the value is never written
+        Ok(unsafe { version.read() })
+    }
+
+    /// Resolve every function of the ABI.
+    ///
+    /// Fails on the first missing symbol, so a partial plugin is never used.
+    pub fn api(&self) -> Result<Api<'_>> {
+        // SAFETY: the types match the declarations in c/plugin.c for
+        // ABI_VERSION, which `open` has checked
+        unsafe {
+            Ok(Api {
+                name: self.symbol("plugin_name")?,
+                add: self.symbol("plugin_add")?,
+                reverse: self.symbol("plugin_reverse")?,
+            })
+        }
+    }
+
+    /// Unload now, reporting errors that dropping would swallow.
+    pub fn close(self) -> Result<()> {
+        self.lib.close().map_err(Error::Close)
+    }
+
+    /// # Safety
+    ///
+    /// `T` must match the C type of the symbol.
+    unsafe fn symbol<T>(&self, name: &'static str) -> Result<Symbol<'_, T>> {
+        self.lib
+            .get(name.as_bytes())
+            .map_err(|source| Error::MissingSymbol {
+                symbol: name,
+                source,
+            })
+    }
+}
+
+// =====================================================
+// Typed Vtable
+// =====================================================
+
+/// The plugin's functions, borrowed from the `Plugin` they came from.
+///
+/// Fields stay private: a `Symbol` derefs to a plain `fn` pointer, and a
+/// copied-out pointer carries no lifetime and would dangle after unload.
+pub struct Api<'lib> {
+    name: Symbol<'lib, NameFn>,
+    add: Symbol<'lib, AddFn>,
+    reverse: Symbol<'lib, ReverseFn>,
+}
+
+impl<'lib> Api<'lib> {
+    /// Plugin name; points into the library's static data, so it is
+    /// borrowed for `'lib` rather than copied.
+    pub fn name(&self) -> &'lib CStr {
+        // SAFETY: plugin_name takes no arguments and returns a pointer to a
+        // static NUL-terminated string that lives as long as the library
+        unsafe { CStr::from_ptr((self.name)()) }
+    }
+
+    pub fn add(&self, a: i32, b: i32) -> i32 {
+        // SAFETY: plain value arguments; plugin_add wraps on overflow
+        unsafe { (self.add)(a, b) }
+    }
+
+    pub fn reverse(&self, buf: &mut [u8]) {
+        // SAFETY: buf is valid and writable for buf.len() bytes
+        unsafe { (self.reverse)(buf.as_mut_ptr(), buf.len()) }
+    }
+}
+
+// =====================================================
+// Usage Example
+// =====================================================
+
+fn main() -> Result<()> {
+    // Plugin path from the command line, else the one build.rs produced
+    let path = std::env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("PLUGIN_PATH")));
+
+    let plugin = Plugin::open(&path)?;
+    {
+        let api = plugin.api()?;
+        println!("Loaded {:?} from {}", api.name(), path.display());
+        println!("2 + 3 = {}", api.add(2, 3));
+
+        let mut word = *b"plugin";
+        api.reverse(&mut word);
+        println!("reversed: {}", String::from_utf8_lossy(&word));
+    }
+    plugin.close()
+}
+
+// =====================================================
+// Tests
+// =====================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot dlopen")]
+    fn test_load_and_call() {
+        let plugin = Plugin::open(env!("PLUGIN_PATH")).unwrap();
+        let api = plugin.api().unwrap();
+
+        assert_eq!(api.name().to_str().unwrap(), "reverser");
+        assert_eq!(api.add(40, 2), 42);
+        assert_eq!(api.add(i32::MAX, 1), i32::MIN);
+
+        let mut buf = *b"abcde";
+        api.reverse(&mut buf);
+        assert_eq!(&buf, b"edcba");
+        api.reverse(&mut []);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot dlopen")]
+    fn test_abi_mismatch_is_rejected() {
+        let err = Plugin::open(env!("PLUGIN_OLD_ABI_PATH")).err().unwrap();
+        assert!(matches!(
+            err,
+            Error::AbiMismatch {
+                found: 1,
+                expected: ABI_VERSION
+            }
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot dlopen")]
+    fn test_missing_symbol_names_it() {
+        let plugin = Plugin::open(env!("PLUGIN_MINIMAL_PATH")).unwrap();
+        let err = plugin.api().err().unwrap();
+
+        assert!(matches!(err, Error::MissingSymbol { symbol: "plugin_reverse", .. }));
+        assert!(err.source().is_some(), "dlerror text is kept as the source");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot dlopen")]
+    fn test_open_missing_file() {
+        let err = Plugin::open("/nonexistent/libplugin.so").err().unwrap();
+        assert!(matches!(&err, Error::Open { path, .. } if path.ends_with("libplugin.so")));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot dlopen")]
+    fn test_reopen_after_close() {
+        let plugin = Plugin::open(env!("PLUGIN_PATH")).unwrap();
+        let name = plugin.api().unwrap().name().to_owned();
+        plugin.close().unwrap();
+
+        // Owned copies survive the unload; borrowed ones could not compile
+        assert_eq!(name.to_str().unwrap(), "reverser");
+        let plugin = Plugin::open(env!("PLUGIN_PATH")).unwrap();
+        assert_eq!(plugin.api().unwrap().add(1, 1), 2);
+    }
+}
@@ -0,0 +1,506 @@
+//! Template for exposing a Rust type to C as an opaque handle
+//!
+//! C sees `Store *` and a set of `store_*` functions. Every entry point
+//! catches panics (ffi-04), reports failures as an error code plus a
+//! thread-local message, and documents who owns each pointer.
+//!
+//! The C header `c/expose_api.h` is generated with cbindgen and checked in:
+//! ```bash
+//! cbindgen --lang c --output c/expose_api.h
+//! ```
+//! `c/expose_api_test.c` exercises the API from C; `build.rs` compiles it
+//! and `test_c_program` runs it against this crate.
+//!
+//! Add to Cargo.toml:
+//! ```toml
+//! [lib]
+//! crate-type = ["cdylib", "staticlib", "rlib"]
+//!
+//! [dependencies]
+//! thiserror = "1"
+//!
+//! [build-dependencies]
+//! cc = "1"
+//! ```
+//!
+//! Ownership rules:
+//! - `const char *` arguments are borrowed for the duration of the call
+//! - `Store *` from `store_new` is owned by the caller; free with `store_free`
+//! - `char *` out-params are owned by the caller; free with `store_string_free`
+//! - byte buffers from `store_export` are freed with `store_buffer_free`
+//! - never pass Rust-allocated memory to C's `free()`, or vice versa
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+// =====================================================
+// Rust Type Being Exposed
+// =====================================================
+
+/// Simple in-memory key/value store; opaque to C.
+#[derive(Debug, Default)]
+pub struct Store {
+    entries: BTreeMap<String, String>,
+}
+
+impl Store {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), StoreError> {
+        if key.is_empty() || key.contains(['=', '\n']) || value.contains('\n') {
+            return Err(StoreError::InvalidKey(key.to_string()));
+        }
+        self.entries.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Result<&str, StoreError> {
+        self.entries
+            .get(key)
+            .map(String::as_str)
+            .ok_or_else(|| StoreError::NotFound(key.to_string()))
+    }
+
+    /// `key=value` lines, sorted by key
+    pub fn export(&self) -> Vec<u8> {
+        self.entries
+            .iter()
+            .flat_map(|(k, v)| format!("{}={}\n", k, v).into_bytes())
+            .collect()
+    }
+}
+
+// =====================================================
+// Error Codes and Last Error
+// =====================================================
+
+pub const STORE_OK: c_int = 0;
+pub const STORE_ERR_NULL_ARGUMENT: c_int = -1;
+pub const STORE_ERR_INVALID_UTF8: c_int = -2;
+pub const STORE_ERR_INVALID_KEY: c_int = -3;
+pub const STORE_ERR_NOT_FOUND: c_int = -4;
+pub const STORE_ERR_BUFFER_TOO_SMALL: c_int = -5;
+pub const STORE_ERR_PANIC: c_int = -99;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("argument `{0}` is null")]
+    NullArgument(&'static str),
+
+    #[error("argument `{0}` is not valid UTF-8")]
+    InvalidUtf8(&'static str),
+
+    #[error("invalid key or value for key {0:?}")]
+    InvalidKey(String),
+
+    #[error("key not found: {0}")]
+    NotFound(String),
+
+    #[error("buffer too small: {required} bytes required")]
+    BufferTooSmall { required: usize },
+}
+
+impl StoreError {
+    fn code(&self) -> c_int {
+        match self {
+            StoreError::NullArgument(_) => STORE_ERR_NULL_ARGUMENT,
+            StoreError::InvalidUtf8(_) => STORE_ERR_INVALID_UTF8,
+            StoreError::InvalidKey(_) => STORE_ERR_INVALID_KEY,
+            StoreError::NotFound(_) => STORE_ERR_NOT_FOUND,
+            StoreError::BufferTooSmall { .. } => STORE_ERR_BUFFER_TOO_SMALL,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Messages come from our own Display impls; strip NULs defensively
+    let message = CString::new(message.replace('\0', "\\0")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Run one entry point: clear the last error, catch panics, map errors to codes.
+fn ffi_boundary<F>(f: F) -> c_int
+where
+    F: FnOnce() -> Result<(), StoreError>,
+{
+    clear_last_error();
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => STORE_OK,
+        Ok(Err(e)) => {
+            let code = e.code();
+            set_last_error(e.to_string());
+            code
+        }
+        Err(payload) => {
+            set_last_error(format!("panic: {}", panic_message(&*payload)));
+            STORE_ERR_PANIC
+        }
+    }
+}
+
+// =====================================================
+// Argument Helpers
+// =====================================================
+
+/// # Safety
+/// `ptr` must be null or a valid NUL-terminated string that outlives `'a`.
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &'static str) -> Result<&'a str, StoreError> {
+    if ptr.is_null() {
+        return Err(StoreError::NullArgument(name));
+    }
+    // SAFETY: non-null and valid per the caller's contract
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|_| StoreError::InvalidUtf8(name))
+}
+
+/// # Safety
+/// `ptr` must be null or a live handle from `store_new`.
+unsafe fn store_ref<'a>(ptr: *const Store) -> Result<&'a Store, StoreError> {
+    // SAFETY: null or a live handle per the caller's contract
+    unsafe { ptr.as_ref() }.ok_or(StoreError::NullArgument("store"))
+}
+
+/// # Safety
+/// `ptr` must be null or a live handle from `store_new`, not used elsewhere.
+unsafe fn store_mut<'a>(ptr: *mut Store) -> Result<&'a mut Store, StoreError> {
+    // SAFETY: null or a live, unaliased handle per the caller's contract
+    unsafe { ptr.as_mut() }.ok_or(StoreError::NullArgument("store"))
+}
+
+// =====================================================
+// Exported API
+// =====================================================
+
+/// Create a store. Returns NULL on failure (see `store_last_error`).
+#[no_mangle]
+pub extern "C" fn store_new() -> *mut Store {
+    let mut store = ptr::null_mut();
+    ffi_boundary(|| {
+        store = Box::into_raw(Box::new(Store::default()));
+        Ok(())
+    });
+    store
+}
+
+/// Destroy a store. NULL is a no-op.
+///
+/// # Safety
+/// `store` must be NULL or a handle from `store_new` not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn store_free(store: *mut Store) {
+    if store.is_null() {
+        return;
+    }
+    ffi_boundary(|| {
+        // SAFETY: handle came from Box::into_raw in store_new
+        drop(unsafe { Box::from_raw(store) });
+        Ok(())
+    });
+}
+
+/// Insert or replace `key`. Both strings are copied.
+///
+/// # Safety
+/// `store` must be a live handle; `key` and `value` NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn store_set(
+    store: *mut Store,
+    key: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    ffi_boundary(|| {
+        // SAFETY: forwarded from this function's contract
+        let (store, key, value) = unsafe {
+            (
+                store_mut(store)?,
+                str_arg(key, "key")?,
+                str_arg(value, "value")?,
+            )
+        };
+        store.set(key, value)
+    })
+}
+
+/// Look up `key` and return a newly allocated copy in `*out`.
+/// Free it with `store_string_free`.
+///
+/// # Safety
+/// `store` must be a live handle, `key` a NUL-terminated string and `out`
+/// a valid pointer to write to.
+#[no_mangle]
+pub unsafe extern "C" fn store_get(
+    store: *const Store,
+    key: *const c_char,
+    out: *mut *mut c_char,
+) -> c_int {
+    ffi_boundary(|| {
+        if out.is_null() {
+            return Err(StoreError::NullArgument("out"));
+        }
+        // SAFETY: forwarded from this function's contract
+        let (store, key) = unsafe { (store_ref(store)?, str_arg(key, "key")?) };
+        let value = CString::new(store.get(key)?).expect("values never contain NUL");
+
+        // SAFETY: out is non-null and writable per the contract
+        unsafe { *out = value.into_raw() };
+        Ok(())
+    })
+}
+
+/// Copy the value for `key` into a caller-provided buffer, NUL-terminated.
+///
+/// `*required` is always set to the needed size including the NUL, so a
+/// caller can retry with a larger buffer after `STORE_ERR_BUFFER_TOO_SMALL`.
+///
+/// # Safety
+/// `buf` must be valid for `buf_len` bytes (or NULL with `buf_len` 0);
+/// `required` must be a valid pointer to write to.
+#[no_mangle]
+pub unsafe extern "C" fn store_get_into(
+    store: *const Store,
+    key: *const c_char,
+    buf: *mut c_char,
+    buf_len: usize,
+    required: *mut usize,
+) -> c_int {
+    ffi_boundary(|| {
+        if required.is_null() {
+            return Err(StoreError::NullArgument("required"));
+        }
+        // SAFETY: forwarded from this function's contract
+        let (store, key) = unsafe { (store_ref(store)?, str_arg(key, "key")?) };
+        let value = store.get(key)?.as_bytes();
+        let needed = value.len() + 1;
+
+        // SAFETY: required is non-null and writable
+        unsafe { *required = needed };
+        if buf.is_null() || buf_len < needed {
+            return Err(StoreError::BufferTooSmall { required: needed });
+        }
+
+        // SAFETY: buf has room for value plus NUL, checked above
+        unsafe {
+            ptr::copy_nonoverlapping(value.as_ptr(), buf as *mut u8, value.len());
+            *buf.add(value.len()) = 0;
+        }
+        Ok(())
+    })
+}
+
+/// Serialize all entries as `key=value\n` lines into a new buffer.
+/// Free it with `store_buffer_free(*out, *out_len)`.
+///
+/// # Safety
+/// `store` must be a live handle; `out` and `out_len` valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn store_export(
+    store: *const Store,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    ffi_boundary(|| {
+        if out.is_null() || out_len.is_null() {
+            return Err(StoreError::NullArgument("out"));
+        }
+        // SAFETY: forwarded from this function's contract
+        let store = unsafe { store_ref(store)? };
+
+        // Box<[u8]> so capacity == len and store_buffer_free can rebuild it
+        let bytes = store.export().into_boxed_slice();
+        let len = bytes.len();
+
+        // SAFETY: out and out_len are non-null and writable
+        unsafe {
+            *out = Box::into_raw(bytes) as *mut u8;
+            *out_len = len;
+        }
+        Ok(())
+    })
+}
+
+/// Free a string returned by `store_get`. NULL is a no-op.
+///
+/// # Safety
+/// `s` must be NULL or a pointer from `store_get` not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn store_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: s came from CString::into_raw in store_get
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Free a buffer returned by `store_export`. NULL is a no-op.
+///
+/// # Safety
+/// `buf`/`len` must be exactly the pair returned by `store_export`.
+#[no_mangle]
+pub unsafe extern "C" fn store_buffer_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        // SAFETY: buf/len came from Box::<[u8]>::into_raw in store_export
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)) });
+    }
+}
+
+/// Message for the last failed call on this thread, or NULL.
+/// Valid until the next `store_*` call on the same thread; do not free.
+#[no_mangle]
+pub extern "C" fn store_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+// =====================================================
+// Tests
+// =====================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Entry point of c/expose_api_test.c, linked by build.rs
+    extern "C" {
+        fn expose_api_c_tests() -> c_int;
+    }
+
+    const HEADER: &str = include_str!("c/expose_api.h");
+
+    fn last_error() -> Option<String> {
+        let ptr = store_last_error();
+        // SAFETY: non-null pointers come from LAST_ERROR and are valid here
+        (!ptr.is_null()).then(|| {
+            unsafe { CStr::from_ptr(ptr) }
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "runs the C test program")]
+    fn test_c_program() {
+        // SAFETY: the C test only uses the documented API
+        assert_eq!(
+            unsafe { expose_api_c_tests() },
+            0,
+            "see stderr for failed checks"
+        );
+    }
+
+    /// The header must declare exactly these prototypes and constants. The
+    /// coercions fail to compile when an export's Rust signature changes, so
+    /// the expected C prototype next to it has to be updated too.
+    #[test]
+    fn test_header_matches_exports() {
+        let _: extern "C" fn() -> *mut Store = store_new;
+        let _: unsafe extern "C" fn(*mut Store) = store_free;
+        let _: unsafe extern "C" fn(*mut Store, *const c_char, *const c_char) -> c_int = store_set;
+        let _: unsafe extern "C" fn(*const Store, *const c_char, *mut *mut c_char) -> c_int =
+            store_get;
+        let _: unsafe extern "C" fn(
+            *const Store,
+            *const c_char,
+            *mut c_char,
+            usize,
+            *mut usize,
+        ) -> c_int = store_get_into;
+        let _: unsafe extern "C" fn(*const Store, *mut *mut u8, *mut usize) -> c_int = store_export;
+        let _: unsafe extern "C" fn(*mut c_char) = store_string_free;
+        let _: unsafe extern "C" fn(*mut u8, usize) = store_buffer_free;
+        let _: extern "C" fn() -> *const c_char = store_last_error;
+
+        let prototypes = [
+            "Store *store_new(void);",
+            "void store_free(Store *store);",
+            "int store_set(Store *store, const char *key, const char *value);",
+            "int store_get(const Store *store, const char *key, char **out);",
+            "int store_get_into(const Store *store, const char *key, char *buf, size_t buf_len, size_t *required);",
+            "int store_export(const Store *store, uint8_t **out, size_t *out_len);",
+            "void store_string_free(char *s);",
+            "void store_buffer_free(uint8_t *buf, size_t len);",
+            "const char *store_last_error(void);",
+        ];
+        let constants = [
+            ("STORE_OK", STORE_OK),
+            ("STORE_ERR_NULL_ARGUMENT", STORE_ERR_NULL_ARGUMENT),
+            ("STORE_ERR_INVALID_UTF8", STORE_ERR_INVALID_UTF8),
+            ("STORE_ERR_INVALID_KEY", STORE_ERR_INVALID_KEY),
+            ("STORE_ERR_NOT_FOUND", STORE_ERR_NOT_FOUND),
+            ("STORE_ERR_BUFFER_TOO_SMALL", STORE_ERR_BUFFER_TOO_SMALL),
+            ("STORE_ERR_PANIC", STORE_ERR_PANIC),
+        ];
+
+        // cbindgen wraps long parameter lists, so compare with whitespace collapsed
+        let header = HEADER.split_whitespace().collect::<Vec<_>>().join(" ");
+        for prototype in prototypes {
+            assert!(header.contains(prototype), "header lacks `{}`", prototype);
+        }
+        for (name, value) in constants {
+            let define = format!("#define {} {}", name, value);
+            assert!(
+                HEADER.lines().any(|line| line == define),
+                "header lacks `{}`",
+                define
+            );
+        }
+
+        // Declarations start in the first column; comments and directives don't
+        let declared = HEADER
+            .lines()
+            .filter(|line| line.contains('(') && !line.starts_with(['#', '/', ' ']))
+            .count();
+        assert_eq!(
+            declared,
+            prototypes.len(),
+            "header declares unlisted functions"
+        );
+        assert_eq!(HEADER.matches("#define STORE_").count(), constants.len());
+    }
+
+    #[test]
+    fn test_panic_becomes_error_code() {
+        let code = ffi_boundary(|| panic!("invariant broken"));
+        assert_eq!(code, STORE_ERR_PANIC);
+        assert_eq!(last_error().as_deref(), Some("panic: invariant broken"));
+
+        // Next call clears it
+        assert_eq!(ffi_boundary(|| Ok(())), STORE_OK);
+        assert_eq!(last_error(), None);
+    }
+
+    #[test]
+    fn test_roundtrip_from_rust() {
+        let store = store_new();
+        let mut out = ptr::null_mut();
+
+        // SAFETY: valid handle and NUL-terminated literals
+        unsafe {
+            assert_eq!(store_set(store, c"k".as_ptr(), c"v".as_ptr()), STORE_OK);
+            assert_eq!(
+                store_set(store, c"a=b".as_ptr(), c"v".as_ptr()),
+                STORE_ERR_INVALID_KEY
+            );
+            assert_eq!(store_get(store, c"k".as_ptr(), &mut out), STORE_OK);
+            assert_eq!(CStr::from_ptr(out).to_str(), Ok("v"));
+            store_string_free(out);
+            store_free(store);
+        }
+    }
+}
@@ -0,0 +1,596 @@
+//! Pure-Rust stand-in for `c/mocklib.c`
+//!
+//! Used by safe-wrapper.rs under Miri, which cannot call into C, and with
+//! the `fake-ffi` feature. Same signatures and behaviour as the C mock, and
+//! memory handed out the way C would: raw pointers into heap allocations,
+//! so Miri checks the wrapper's pointer handling against real provenance.
+//!
+//! State that C keeps in `_Thread_local` variables is thread-local here too.
+
+use super::ffi::{
+    Callback, Completion, Handle, LibConfig, LibOption, Point, LIB_EBUSY, LIB_EERRNO, LIB_EFAIL,
+    LIB_EINVAL, LIB_ERANGE, LIB_ETOOLONG,
+};
+use std::cell::{Cell, RefCell};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+const NAME_CAPACITY: usize = 64;
+
+struct State {
+    result: Option<CString>,
+    points: Vec<Point>,
+    callback: Option<Callback>,
+    user_data: *mut c_void,
+    name: CString,
+    max_input_len: c_uint,
+    timeout_ms: c_uint,
+    lowercase: bool,
+    option_count: usize,
+}
+
+/// What a `*mut Handle` really points to.
+///
+/// Mutable state sits behind a mutex so overlapping calls (which the C
+/// contract forbids, and one test makes on purpose) are detected through the
+/// atomics without the fake itself creating aliasing `&mut`s.
+struct FakeHandle {
+    state: Mutex<State>,
+    in_process: AtomicI32,
+    violations: AtomicI32,
+    in_hash: AtomicI32,
+    max_hash_concurrency: AtomicI32,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+    static FAIL_NEXT_CREATE: Cell<bool> = const { Cell::new(false) };
+    static LIVE_HANDLES: Cell<c_int> = const { Cell::new(0) };
+    static OUTSTANDING_ALLOCS: Cell<c_int> = const { Cell::new(0) };
+}
+
+static PENDING_ASYNC: AtomicI32 = AtomicI32::new(0);
+
+fn pause_briefly() {
+    thread::sleep(Duration::from_micros(200));
+}
+
+fn set_error(message: impl Into<Vec<u8>>) {
+    let message = CString::new(message).expect("error messages have no NUL");
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+#[cfg(target_os = "linux")]
+fn set_errno(value: c_int) {
+    extern "C" {
+        fn __errno_location() -> *mut c_int;
+    }
+    // SAFETY: __errno_location returns this thread's errno slot
+    unsafe { *__errno_location() = value }
+}
+
+/// # Safety
+///
+/// `handle` must come from `lib_create` and not be destroyed.
+unsafe fn handle_ref<'a>(handle: *mut Handle) -> &'a FakeHandle {
+    &*handle.cast::<FakeHandle>()
+}
+
+fn lock(handle: &FakeHandle) -> MutexGuard<'_, State> {
+    handle.state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Copy `bytes` plus a NUL into `buf`, truncated to `len` like snprintf.
+///
+/// # Safety
+///
+/// `buf` must be writable for `len` bytes (or `len` is 0).
+unsafe fn write_truncated(buf: *mut c_char, len: usize, bytes: &[u8]) {
+    if len == 0 {
+        return;
+    }
+    let n = bytes.len().min(len - 1);
+    ptr::copy_nonoverlapping(bytes.as_ptr(), buf.cast::<u8>(), n);
+    *buf.add(n) = 0;
+}
+
+fn transform(input: &[u8], lowercase: bool) -> CString {
+    let output = if lowercase {
+        input.to_ascii_lowercase()
+    } else {
+        input.to_ascii_uppercase()
+    };
+    CString::new(output).expect("input came from a C string")
+}
+
+pub unsafe extern "C" fn lib_create() -> *mut Handle {
+    if FAIL_NEXT_CREATE.with(|f| f.replace(false)) {
+        set_error("create failed (injected)");
+        return ptr::null_mut();
+    }
+
+    let handle = Box::new(FakeHandle {
+        state: Mutex::new(State {
+            result: None,
+            points: Vec::new(),
+            callback: None,
+            user_data: ptr::null_mut(),
+            name: CString::default(),
+            max_input_len: 0,
+            timeout_ms: 0,
+            lowercase: false,
+            option_count: 0,
+        }),
+        in_process: AtomicI32::new(0),
+        violations: AtomicI32::new(0),
+        in_hash: AtomicI32::new(0),
+        max_hash_concurrency: AtomicI32::new(0),
+    });
+    LIVE_HANDLES.with(|n| n.set(n.get() + 1));
+    Box::into_raw(handle).cast()
+}
+
+pub unsafe extern "C" fn lib_create_with_config(config: *const LibConfig) -> *mut Handle {
+    let Some(config) = config.as_ref() else {
+        set_error("config is null");
+        return ptr::null_mut();
+    };
+    if config.struct_size < std::mem::size_of::<LibConfig>() {
+        set_error("config struct_size too small");
+        return ptr::null_mut();
+    }
+    let name = if config.name.is_null() {
+        CString::default()
+    } else {
+        CStr::from_ptr(config.name).to_owned()
+    };
+    if name.as_bytes().len() >= NAME_CAPACITY {
+        set_error("name too long");
+        return ptr::null_mut();
+    }
+
+    let options: &[LibOption] = if config.option_count == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(config.options, config.option_count)
+    };
+    let mut lowercase = false;
+    for option in options {
+        if option.key.is_null() || option.value.is_null() {
+            set_error("option key or value is null");
+            return ptr::null_mut();
+        }
+        let key = CStr::from_ptr(option.key).to_bytes();
+        let value = CStr::from_ptr(option.value).to_bytes();
+        match (key, value) {
+            (b"mode", b"lower") => lowercase = true,
+            (b"mode", b"upper") => lowercase = false,
+            (b"mode", _) => {
+                set_error("invalid value for option: mode");
+                return ptr::null_mut();
+            }
+            _ => {
+                let mut message = b"unknown option: ".to_vec();
+                message.extend_from_slice(key);
+                set_error(message);
+                return ptr::null_mut();
+            }
+        }
+    }
+
+    let handle = lib_create();
+    if handle.is_null() {
+        return handle;
+    }
+    let mut state = lock(handle_ref(handle));
+    state.name = name;
+    state.max_input_len = config.max_input_len;
+    state.timeout_ms = config.timeout_ms;
+    state.lowercase = lowercase;
+    state.option_count = config.option_count;
+    drop(state);
+    handle
+}
+
+pub unsafe extern "C" fn lib_destroy(handle: *mut Handle) {
+    if handle.is_null() {
+        return;
+    }
+    drop(Box::from_raw(handle.cast::<FakeHandle>()));
+    LIVE_HANDLES.with(|n| n.set(n.get() - 1));
+}
+
+pub unsafe extern "C" fn lib_process(handle: *mut Handle, input: *const c_char) -> c_int {
+    if handle.is_null() || input.is_null() {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    let handle = handle_ref(handle);
+
+    if handle.in_process.fetch_add(1, Ordering::AcqRel) != 0 {
+        handle.violations.fetch_add(1, Ordering::Relaxed);
+        handle.in_process.fetch_sub(1, Ordering::AcqRel);
+        set_error("concurrent call on non-reentrant handle");
+        return LIB_EBUSY;
+    }
+    pause_briefly();
+    let rc = process_locked(handle, CStr::from_ptr(input).to_bytes());
+    handle.in_process.fetch_sub(1, Ordering::AcqRel);
+    rc
+}
+
+fn process_locked(handle: &FakeHandle, input: &[u8]) -> c_int {
+    let mut state = lock(handle);
+    match input {
+        b"fail" => {
+            set_error("processing failed: input rejected");
+            return LIB_EFAIL;
+        }
+        b"fail-bad-utf8" => {
+            set_error(&b"bad \xff\xfe message"[..]);
+            return LIB_EFAIL;
+        }
+        #[cfg(target_os = "linux")]
+        b"fail-io" => {
+            set_error("write to backing store failed");
+            set_errno(5); // EIO
+            return LIB_EERRNO;
+        }
+        b"fail-unknown" => {
+            set_error("code from a newer library version");
+            return 42;
+        }
+        b"null-result" => {
+            state.result = None;
+            return 0;
+        }
+        b"bad-utf8" => {
+            state.result = Some(CString::new(&b"\xc3\x28"[..]).unwrap());
+            return 0;
+        }
+        _ => {}
+    }
+
+    if state.max_input_len != 0 && input.len() > state.max_input_len as usize {
+        set_error("input too long");
+        return LIB_ETOOLONG;
+    }
+    state.result = Some(transform(input, state.lowercase));
+    0
+}
+
+pub unsafe extern "C" fn lib_hash(
+    handle: *mut Handle,
+    input: *const c_char,
+    out: *mut u64,
+) -> c_int {
+    if handle.is_null() || input.is_null() || out.is_null() {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    let handle = handle_ref(handle);
+
+    let now = handle.in_hash.fetch_add(1, Ordering::AcqRel) + 1;
+    handle.max_hash_concurrency.fetch_max(now, Ordering::AcqRel);
+    pause_briefly();
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in CStr::from_ptr(input).to_bytes() {
+        hash = (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3);
+    }
+    *out = hash;
+
+    handle.in_hash.fetch_sub(1, Ordering::AcqRel);
+    0
+}
+
+pub unsafe extern "C" fn lib_get_result(handle: *mut Handle) -> *const c_char {
+    if handle.is_null() {
+        return ptr::null();
+    }
+    lock(handle_ref(handle))
+        .result
+        .as_ref()
+        .map_or(ptr::null(), |r| r.as_ptr())
+}
+
+pub unsafe extern "C" fn lib_get_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+pub unsafe extern "C" fn lib_get_name(
+    handle: *mut Handle,
+    buf: *mut c_char,
+    len: *mut usize,
+) -> c_int {
+    if handle.is_null() || len.is_null() {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    let state = lock(handle_ref(handle));
+    let name = state.name.as_bytes_with_nul();
+    if buf.is_null() || *len < name.len() {
+        *len = name.len();
+        return LIB_ERANGE;
+    }
+    ptr::copy_nonoverlapping(name.as_ptr(), buf.cast::<u8>(), name.len());
+    *len = name.len();
+    0
+}
+
+pub unsafe extern "C" fn lib_copy_result(
+    handle: *mut Handle,
+    buf: *mut c_char,
+    len: usize,
+) -> c_int {
+    if handle.is_null() || buf.is_null() {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    let state = lock(handle_ref(handle));
+    let Some(result) = &state.result else {
+        set_error("no result available");
+        return LIB_EFAIL;
+    };
+    let bytes = result.as_bytes_with_nul();
+    if len < bytes.len() {
+        return LIB_ERANGE;
+    }
+    ptr::copy_nonoverlapping(bytes.as_ptr(), buf.cast::<u8>(), bytes.len());
+    0
+}
+
+pub unsafe extern "C" fn lib_format_result(
+    handle: *mut Handle,
+    buf: *mut c_char,
+    len: usize,
+) -> c_int {
+    if handle.is_null() || (buf.is_null() && len != 0) {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    let state = lock(handle_ref(handle));
+    let Some(result) = &state.result else {
+        set_error("no result available");
+        return LIB_EFAIL;
+    };
+    let mut formatted = b"result=".to_vec();
+    formatted.extend_from_slice(result.as_bytes());
+    write_truncated(buf, len, &formatted);
+    formatted.len() as c_int
+}
+
+pub unsafe extern "C" fn lib_sum(values: *const i32, len: usize) -> i64 {
+    (0..len).map(|i| i64::from(*values.add(i))).sum()
+}
+
+pub unsafe extern "C" fn lib_scale(values: *mut i32, len: usize, factor: i32) {
+    for i in 0..len {
+        let value = values.add(i);
+        *value = (*value).wrapping_mul(factor);
+    }
+}
+
+pub unsafe extern "C" fn lib_translate(
+    points: *mut Point,
+    count: c_int,
+    dx: f64,
+    dy: f64,
+) -> c_int {
+    if count < 0 || (points.is_null() && count > 0) {
+        set_error("invalid point array");
+        return LIB_EINVAL;
+    }
+    for i in 0..count as usize {
+        let point = points.add(i);
+        (*point).x += dx;
+        (*point).y += dy;
+    }
+    0
+}
+
+pub unsafe extern "C" fn lib_set_points(
+    handle: *mut Handle,
+    points: *const Point,
+    count: usize,
+) -> c_int {
+    if handle.is_null() || (points.is_null() && count > 0) {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    let copy = if count == 0 {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(points, count).to_vec()
+    };
+    lock(handle_ref(handle)).points = copy;
+    0
+}
+
+pub unsafe extern "C" fn lib_get_points(handle: *mut Handle, count: *mut usize) -> *const Point {
+    if handle.is_null() || count.is_null() {
+        return ptr::null();
+    }
+    let state = lock(handle_ref(handle));
+    *count = state.points.len();
+    if state.points.is_empty() {
+        ptr::null()
+    } else {
+        state.points.as_ptr()
+    }
+}
+
+pub unsafe extern "C" fn lib_describe(handle: *mut Handle) -> *mut c_char {
+    if handle.is_null() {
+        set_error("null argument");
+        return ptr::null_mut();
+    }
+    let state = lock(handle_ref(handle));
+    let mut description = b"handle '".to_vec();
+    description.extend_from_slice(state.name.as_bytes());
+    description.extend_from_slice(b"', last result '");
+    description.extend_from_slice(state.result.as_deref().map_or(&[][..], CStr::to_bytes));
+    description.extend_from_slice(b"'");
+
+    OUTSTANDING_ALLOCS.with(|n| n.set(n.get() + 1));
+    CString::new(description).unwrap().into_raw()
+}
+
+pub unsafe extern "C" fn lib_free(ptr: *mut c_void) {
+    if !ptr.is_null() {
+        OUTSTANDING_ALLOCS.with(|n| n.set(n.get() - 1));
+        // Only strings from lib_describe are freed here
+        drop(CString::from_raw(ptr.cast()));
+    }
+}
+
+pub unsafe extern "C" fn lib_set_callback(
+    handle: *mut Handle,
+    callback: Option<Callback>,
+    user_data: *mut c_void,
+) {
+    if handle.is_null() {
+        return;
+    }
+    let mut state = lock(handle_ref(handle));
+    state.callback = callback;
+    state.user_data = if callback.is_some() {
+        user_data
+    } else {
+        ptr::null_mut()
+    };
+}
+
+pub unsafe extern "C" fn lib_emit(handle: *mut Handle, count: c_int) -> c_int {
+    let registered = if handle.is_null() {
+        None
+    } else {
+        let state = lock(handle_ref(handle));
+        state.callback.map(|callback| (callback, state.user_data))
+    };
+    let Some((callback, user_data)) = registered else {
+        set_error("no callback registered");
+        return LIB_EINVAL;
+    };
+
+    // Not under the lock: C would not hold one across the callback either
+    for i in 0..count {
+        callback(user_data, i);
+    }
+    count
+}
+
+/// `user_data` moved to the worker thread, as C would pass it.
+struct SendPtr(*mut c_void);
+
+// SAFETY: the pointer is only handed back to the completion, whose owner
+// promised it may be used from a library thread
+unsafe impl Send for SendPtr {}
+
+pub unsafe extern "C" fn lib_process_async(
+    handle: *mut Handle,
+    input: *const c_char,
+    completion: Completion,
+    user_data: *mut c_void,
+) -> c_int {
+    if handle.is_null() || input.is_null() {
+        set_error("null argument");
+        return LIB_EINVAL;
+    }
+    let input = CStr::from_ptr(input).to_bytes().to_vec();
+    let lowercase = lock(handle_ref(handle)).lowercase;
+    let user_data = SendPtr(user_data);
+
+    PENDING_ASYNC.fetch_add(1, Ordering::AcqRel);
+    thread::spawn(move || {
+        let user_data = user_data;
+        pause_briefly();
+
+        if input == b"fail" {
+            let message = c"processing failed: input rejected";
+            completion(user_data.0, LIB_EFAIL, ptr::null(), message.as_ptr());
+        } else {
+            let result = transform(&input, lowercase);
+            completion(user_data.0, 0, result.as_ptr(), ptr::null());
+        }
+        PENDING_ASYNC.fetch_sub(1, Ordering::AcqRel);
+    });
+    0
+}
+
+// Test hooks, as exported by c/mocklib.c
+#[cfg(test)]
+pub use hooks::*;
+
+#[cfg(test)]
+mod hooks {
+    use super::*;
+
+    pub unsafe extern "C" fn mock_fail_next_create() {
+        FAIL_NEXT_CREATE.with(|f| f.set(true));
+    }
+
+    pub unsafe extern "C" fn mock_live_handles() -> c_int {
+        LIVE_HANDLES.with(Cell::get)
+    }
+
+    pub unsafe extern "C" fn mock_outstanding_allocs() -> c_int {
+        OUTSTANDING_ALLOCS.with(Cell::get)
+    }
+
+    pub unsafe extern "C" fn mock_pending_async() -> c_int {
+        PENDING_ASYNC.load(Ordering::Acquire)
+    }
+
+    pub unsafe extern "C" fn mock_get_points_misaligned(
+        handle: *mut Handle,
+        count: *mut usize,
+    ) -> *const Point {
+        let points = lib_get_points(handle, count);
+        if points.is_null() {
+            points
+        } else {
+            points.cast::<u8>().wrapping_add(1).cast()
+        }
+    }
+
+    pub unsafe extern "C" fn mock_sizeof_point() -> usize {
+        std::mem::size_of::<Point>()
+    }
+
+    pub unsafe extern "C" fn mock_config_summary(
+        handle: *mut Handle,
+        buf: *mut c_char,
+        len: usize,
+    ) {
+        let state = lock(handle_ref(handle));
+        let summary = format!(
+            "name={} max_input_len={} timeout_ms={} options={}",
+            state.name.to_string_lossy(),
+            state.max_input_len,
+            state.timeout_ms,
+            state.option_count
+        );
+        write_truncated(buf, len, summary.as_bytes());
+    }
+
+    pub unsafe extern "C" fn mock_sizeof_config() -> usize {
+        std::mem::size_of::<LibConfig>()
+    }
+
+    pub unsafe extern "C" fn mock_handle_violations(handle: *mut Handle) -> c_int {
+        handle_ref(handle).violations.load(Ordering::Acquire)
+    }
+
+    pub unsafe extern "C" fn mock_handle_max_concurrency(handle: *mut Handle) -> c_int {
+        handle_ref(handle)
+            .max_hash_concurrency
+            .load(Ordering::Acquire)
+    }
+}